@@ -0,0 +1,238 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use minifs_extractor::{DecompressedFile, MiniFs};
+use serde::{Deserialize, Serialize};
+
+use crate::hash;
+
+pub fn write_csv(file_name: &str, minifs: &MiniFs) -> std::io::Result<()> {
+    let mut output = BufWriter::new(File::create(file_name)?);
+    writeln!(output, "path,filename,size,chunk,offset_in_chunk")?;
+    for file in minifs.list() {
+        writeln!(
+            output,
+            "{},{},{},{},{}",
+            csv_field(&file.path),
+            csv_field(&file.filename),
+            file.entry.file_size,
+            file.entry.chunk_number,
+            file.entry.offset_in_chunk
+        )?;
+    }
+    output.flush()
+}
+
+/// Quotes a field if it contains characters that would break the CSV row
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One line of a `list --jsonl` listing, or of a --manifest with the SHA-256 of the data
+#[derive(Serialize, Deserialize)]
+pub struct JsonEntry {
+    pub path: String,
+    pub filename: String,
+    pub size: u64,
+    pub chunk: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Writes one JSON object per entry and per line into `output`, as the entries are read
+pub fn write_jsonl(output: &mut impl Write, minifs: &MiniFs) -> std::io::Result<()> {
+    for file in minifs.iter_files() {
+        write_json_line(
+            output,
+            &JsonEntry {
+                size: file.entry.file_size as u64,
+                chunk: file.entry.chunk_number,
+                path: file.path,
+                filename: file.filename,
+                sha256: None,
+            },
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes the same JSON line as [`write_jsonl`] for an extracted file, along with the SHA-256 of
+/// its data, for later use with --changed-since
+pub fn write_manifest_entry(
+    output: &mut impl Write,
+    file: &DecompressedFile,
+) -> std::io::Result<()> {
+    write_json_line(
+        output,
+        &JsonEntry {
+            path: file.path.clone(),
+            filename: file.filename.clone(),
+            size: file.data.len() as u64,
+            chunk: file.chunks.start,
+            sha256: Some(hash::sha256_hex(&file.data)),
+        },
+    )
+}
+
+fn write_json_line(output: &mut impl Write, entry: &JsonEntry) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *output, entry)?;
+    writeln!(output)
+}
+
+/// Inserts one row per entry into the `files` table of the SQLite database `file_name`, creating it
+/// if needed
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(file_name: &str, minifs: &MiniFs) -> rusqlite::Result<()> {
+    let mut connection = rusqlite::Connection::open(file_name)?;
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS files (path TEXT, filename TEXT, size INTEGER, chunk INTEGER, offset_in_chunk INTEGER)",
+        (),
+    )?;
+
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare("INSERT INTO files VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        for file in minifs.list() {
+            insert.execute((
+                &file.path,
+                &file.filename,
+                file.entry.file_size,
+                file.entry.chunk_number,
+                file.entry.offset_in_chunk,
+            ))?;
+        }
+    }
+    transaction.commit()
+}
+
+/// Writes what is needed to extract any file later without parsing the image again: one
+/// `chunk<TAB>offset<TAB>size<TAB>decompressed size` line per chunk, with offsets from the start of
+/// the input, followed by one `file<TAB>chunk<TAB>offset in chunk<TAB>size<TAB>path/filename` line
+/// per entry
+pub fn write_index(file_name: &str, minifs: &MiniFs) -> std::io::Result<()> {
+    let mut output = BufWriter::new(File::create(file_name)?);
+    for (chunk_number, (chunk, _)) in minifs.chunks().enumerate() {
+        let start = minifs
+            .chunk_bounds(chunk_number)
+            .map_or(0, |x| x.start + minifs.get_header_start());
+        writeln!(
+            output,
+            "chunk\t{}\t{}\t{}",
+            start, chunk.chunk_size, chunk.decompressed_size
+        )?;
+    }
+    for file in minifs.list() {
+        writeln!(
+            output,
+            "file\t{}\t{}\t{}\t{}",
+            file.entry.chunk_number,
+            file.entry.offset_in_chunk,
+            file.entry.file_size,
+            file.full_path()
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes one `index<TAB>path/filename` line per entry, in TOF order
+pub fn write_index_map(file_name: &str, minifs: &MiniFs) -> std::io::Result<()> {
+    let mut output = BufWriter::new(File::create(file_name)?);
+    for (index, file) in minifs.list().iter().enumerate() {
+        writeln!(output, "{}\t{}", index, file.full_path())?;
+    }
+    output.flush()
+}
+
+/// Writes the data of all files back to back into `file_name`, along with a
+/// `<file_name>.manifest` holding one `path/filename<TAB>offset<TAB>length` line per file
+pub fn write_pack(file_name: &str, files: &[DecompressedFile]) -> std::io::Result<()> {
+    let mut pack = BufWriter::new(File::create(file_name)?);
+    let mut manifest = BufWriter::new(File::create(format!("{}.manifest", file_name))?);
+
+    let mut offset = 0_u64;
+    for file in files {
+        pack.write_all(&file.data)?;
+        writeln!(
+            manifest,
+            "{}\t{}\t{}",
+            file.full_path(),
+            offset,
+            file.data.len()
+        )?;
+        offset += file.data.len() as u64;
+    }
+
+    pack.flush()?;
+    manifest.flush()
+}
+
+/// Writes one `path/filename<TAB>sha256` line per file, sorted by path, to diff two images
+pub fn write_hashes(file_name: &str, files: &[DecompressedFile]) -> std::io::Result<()> {
+    let mut hashes = files
+        .iter()
+        .map(|x| (x.full_path(), hash::sha256_hex(&x.data)))
+        .collect::<Vec<(String, String)>>();
+    hashes.sort();
+
+    let mut output = BufWriter::new(File::create(file_name)?);
+    for (path, digest) in hashes {
+        writeln!(output, "{}\t{}", path, digest)?;
+    }
+    output.flush()
+}
+
+/// Writes one `path<TAB>original filename` line per file whose name was encoded, with paths
+/// relative to the output directory and the original names escaped
+pub fn write_encoded_names(
+    file_name: &str,
+    encoded_names: &[(PathBuf, String)],
+) -> std::io::Result<()> {
+    let mut output = BufWriter::new(File::create(file_name)?);
+    for (file_path, original) in encoded_names {
+        writeln!(
+            output,
+            "{}\t{}",
+            file_path.to_string_lossy(),
+            original.escape_debug()
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes a pseudo file, in the syntax of `mksquashfs -pf`, setting root ownership on every
+/// extracted file and on the directories holding them. `file_paths` are relative to the output
+/// directory. minifs doesn't store any ownership or mode, so directories get 755 and files 644.
+pub fn write_fakeroot_manifest(file_name: &str, file_paths: &[PathBuf]) -> std::io::Result<()> {
+    let directories = file_paths
+        .iter()
+        .flat_map(|x| x.ancestors().skip(1))
+        .filter(|x| !x.as_os_str().is_empty())
+        .collect::<BTreeSet<&Path>>();
+
+    let mut output = BufWriter::new(File::create(file_name)?);
+    for directory in directories {
+        writeln!(output, "{} m 755 0 0", pseudo_path(directory))?;
+    }
+    for file_path in file_paths {
+        writeln!(output, "{} m 644 0 0", pseudo_path(file_path))?;
+    }
+    output.flush()
+}
+
+/// Quotes a path if it contains characters that would split the pseudo file definition
+fn pseudo_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path.contains([' ', '\t', '"', '\\']) {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path.into_owned()
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::Read;
+
+use minifs_extractor::DecompressedFile;
+use sha2::{Digest, Sha256};
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub fn sha256_reader(reader: &mut impl Read) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0_u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(to_hex(&hasher.finalize()));
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Digest of a whole filesystem: the SHA-256 of the `path/filename`, NUL byte and SHA-256 of
+/// the data of every file, sorted by path. It only depends on the file contents, not on how they
+/// are laid out in chunks, so files can be added in any order as they are extracted.
+#[derive(Default)]
+pub struct MerkleTree {
+    leaves: Vec<(String, Vec<u8>)>,
+}
+
+impl MerkleTree {
+    pub fn add(&mut self, file: &DecompressedFile) {
+        self.leaves
+            .push((file.full_path(), Sha256::digest(&file.data).to_vec()));
+    }
+
+    pub fn root(mut self) -> String {
+        self.leaves.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut root = Sha256::new();
+        for (path, digest) in self.leaves {
+            root.update(path.as_bytes());
+            root.update([0]);
+            root.update(digest);
+        }
+
+        to_hex(&root.finalize())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
@@ -0,0 +1,209 @@
+//! Parser and extractor for minifs filesystems, which are common with VxWorks images.
+use std::fmt;
+
+use lzma::LzmaError;
+
+mod minifs;
+mod output;
+
+pub use minifs::{
+    decompress_chunk, is_valid, path_components, scan_magics, ChunkTiming, DecompressedFile,
+    Endianness, Extracted, FileInfo, MagicCandidate, MiniFs, MiniFsOptions, Parsed, ToCEntry,
+    ToFEntry, HEADER_SIZE, LZMA_CONFIGURATION_WORD, TOC_ENTRY_SIZE, TOF_ENTRY_SIZE,
+};
+pub use output::{
+    output_paths, write_atomic, write_files, ExtractionOptions, ExtractionProblem,
+    ExtractionReport, OutputTree, WRITE_PIECE_SIZE,
+};
+
+#[derive(Debug)]
+pub enum ParseError {
+    EmptyInput,
+    InvalidHeader,
+    UnsupportedVersion,
+    OffsetOverflow,
+    InvalidNameOffset,
+    UnterminatedString,
+    RegionsOutOfOrder,
+    OverlappingChunks,
+    ChunkNumberOutOfRange {
+        chunk_number: u32,
+        chunks_no: u32,
+    },
+    FileOffsetBeyondChunk {
+        path: String,
+        offset_in_chunk: u32,
+        decompressed_size: u32,
+    },
+    /// The LZMA decoder rejected a chunk
+    Decompression(LzmaError),
+    /// A chunk decompressed to less than the size declared in its Table of Chunks entry
+    ShortChunk {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::EmptyInput => write!(f, "Empty input file"),
+            ParseError::InvalidHeader => write!(f, "Invalid minifs header"),
+            ParseError::UnsupportedVersion => write!(f, "Unsupported minifs version"),
+            ParseError::OffsetOverflow => write!(f, "Table offsets overflow the address space"),
+            ParseError::InvalidNameOffset => {
+                write!(f, "A name offset points outside of the Table of Names")
+            }
+            ParseError::UnterminatedString => {
+                write!(f, "A name isn't NUL-terminated within the scan limit")
+            }
+            ParseError::RegionsOutOfOrder => write!(
+                f,
+                "The header, names, files, chunk table and chunks regions overlap or are out of order"
+            ),
+            ParseError::OverlappingChunks => write!(f, "Two chunks overlap in the image"),
+            ParseError::ChunkNumberOutOfRange {
+                chunk_number,
+                chunks_no,
+            } => write!(
+                f,
+                "A file is stored in chunk {} but the Table of Chunks has {} entries",
+                chunk_number, chunks_no
+            ),
+            ParseError::FileOffsetBeyondChunk {
+                path,
+                offset_in_chunk,
+                decompressed_size,
+            } => write!(
+                f,
+                "{} starts at offset {} of a chunk decompressing to {} bytes",
+                path, offset_in_chunk, decompressed_size
+            ),
+            ParseError::Decompression(e) => write!(f, "Couldn't decompress LZMA chunk: {}", e),
+            ParseError::ShortChunk { expected, actual } => write!(
+                f,
+                "LZMA chunk decompressed to {} bytes instead of {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Decompression(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Recoverable anomaly found while parsing or decompressing, the filesystem can still be extracted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// A `MINIFS` magic at this offset didn't start a valid filesystem
+    SkippedMagic(usize),
+    /// Bytes before the minifs header
+    SkippedPadding(usize),
+    /// The first chunk doesn't start with the LZMA configuration word
+    Unvalidated,
+    /// The image is shorter than its tables describe
+    Truncated { available: u64, expected: u64 },
+    /// Bytes after the last chunk
+    TrailingData(u64),
+    /// Entries using the same name as their path and filename, which may be full paths
+    SharedNameOffsets(usize),
+    /// A chunk decompressed to more than its declared size, the extra bytes were dropped
+    OversizedChunk {
+        chunk_number: u32,
+        size: usize,
+        expected: usize,
+    },
+    /// A chunk only partially decompressed, see `MiniFsOptions::tolerate_size_mismatch`
+    PartialChunk {
+        chunk_number: u32,
+        size: usize,
+        expected: usize,
+    },
+    /// Only the start of a file was in its partial chunk
+    PartialFile {
+        path: String,
+        recovered: usize,
+        size: usize,
+    },
+    /// None of a file was in its partial chunk, it isn't returned
+    MissingFile(String),
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::SkippedMagic(offset) => {
+                write!(f, "Skipped invalid minifs header at {:#x}", offset)
+            }
+            ParseWarning::SkippedPadding(bytes) => {
+                write!(
+                    f,
+                    "Skipped {} bytes of padding before the minifs header",
+                    bytes
+                )
+            }
+            ParseWarning::Unvalidated => {
+                write!(
+                    f,
+                    "First chunk doesn't start with the LZMA configuration word"
+                )
+            }
+            ParseWarning::Truncated {
+                available,
+                expected,
+            } => write!(
+                f,
+                "Image is {} bytes but its tables describe {} bytes, it may be truncated",
+                available, expected
+            ),
+            ParseWarning::TrailingData(bytes) => {
+                write!(
+                    f,
+                    "Image has {} bytes of extra data after the last chunk",
+                    bytes
+                )
+            }
+            ParseWarning::SharedNameOffsets(files_no) => write!(
+                f,
+                "{} entries use the same name as path and filename, it may be their full path",
+                files_no
+            ),
+            ParseWarning::OversizedChunk {
+                chunk_number,
+                size,
+                expected,
+            } => write!(
+                f,
+                "LZMA chunk {} decompressed to {} bytes instead of {}, truncating",
+                chunk_number, size, expected
+            ),
+            ParseWarning::PartialChunk {
+                chunk_number,
+                size,
+                expected,
+            } => write!(
+                f,
+                "LZMA chunk {} only decompressed to {} of its {} bytes, keeping the partial chunk",
+                chunk_number, size, expected
+            ),
+            ParseWarning::PartialFile {
+                path,
+                recovered,
+                size,
+            } => write!(
+                f,
+                "Only recovered {} of the {} bytes of {}",
+                recovered, size, path
+            ),
+            ParseWarning::MissingFile(path) => {
+                write!(f, "Skipping {}, its chunk is too short", path)
+            }
+        }
+    }
+}
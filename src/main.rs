@@ -1,89 +1,2104 @@
-mod minifs;
+mod export;
+mod hash;
+#[cfg(feature = "mount")]
+mod mount;
 use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
     fs::File,
-    io::{Read, Write},
-    path::{Component, Path, PathBuf},
-    process::exit,
+    io::{BufWriter, ErrorKind, IsTerminal, Read, Seek, SeekFrom, Write},
+    ops::Deref,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    process::{self, exit, ExitStatus, Stdio},
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
 };
 
-use clap::Parser;
-use minifs::MiniFs;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use glob::{MatchOptions, Pattern};
+use minifs_extractor::{
+    output_paths, path_components, write_atomic, ChunkTiming, DecompressedFile, ExtractionOptions,
+    ExtractionProblem, ExtractionReport, FileInfo, MiniFs, MiniFsOptions, OutputTree, ParseWarning,
+    Parsed, WRITE_PIECE_SIZE,
+};
+
+/// Trailing bytes tolerated after the last chunk before warning, to allow for erase block padding
+const TRAILING_DATA_TOLERANCE: u64 = 64 * 1024;
+
+/// How often --follow checks the binary for new images
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Length of the `MINIFS` magic, the bytes --follow keeps in case one is only partly written
+const HEADER_MAGIC_LEN: usize = 6;
+
+/// Share of printable bytes above which --text-mode treats a file as text
+const TEXT_PRINTABLE_PERCENT: usize = 98;
+
+/// Number of hex digits of the input's SHA-256 used to name the output directory with --hash-output
+const HASH_OUTPUT_PREFIX_LEN: usize = 16;
+
+/// Set when extracting several binaries, see `fail`
+static BATCH_MODE: AtomicBool = AtomicBool::new(false);
 
-#[derive(Debug)]
-enum ParseError {
-    InvalidHeader,
-    UnsupportedVersion,
+/// Set from --color before anything is printed
+static COLOR: OnceLock<ColorChoice> = OnceLock::new();
+
+/// Set when stdout is reserved for machine-readable output
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Set from --show-config, the configuration resolved for each binary is printed too
+static SHOW_CONFIG: AtomicBool = AtomicBool::new(false);
+
+/// Set from --strict, warnings then fail, see `check_strict`
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Number of warnings printed for the current binary
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Prints a `[+]`/`[-]` status line, on stderr if stdout is reserved for machine-readable output
+macro_rules! status {
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        if STATUS_TO_STDERR.load(Ordering::Relaxed) {
+            eprintln!("{}", colorize(line, std::io::stderr().is_terminal()));
+        } else {
+            println!("{}", colorize(line, std::io::stdout().is_terminal()));
+        }
+    }};
 }
 
-#[derive(Parser)]
+/// Prints a `[!]` status line and counts it, so --strict fails once the current step is done
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        WARNINGS.fetch_add(1, Ordering::Relaxed);
+        status!("[!] {}", format!($($arg)*));
+    }};
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Colors the `[+]`/`[-]`/`[!]` prefix of a status line, if enabled for the stream it goes to
+fn colorize(line: String, is_terminal: bool) -> String {
+    let enabled = match COLOR.get().copied().unwrap_or(ColorChoice::Auto) {
+        ColorChoice::Auto => is_terminal,
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    let color = match line.get(..3) {
+        Some("[+]") => "32",
+        Some("[-]") => "31",
+        Some("[!]") => "33",
+        _ => return line,
+    };
+    if !enabled {
+        return line;
+    }
+
+    format!("\x1b[{}m{}\x1b[0m{}", color, &line[..3], &line[3..])
+}
+
+#[derive(Parser, Debug)]
 #[command(author = "Tudor Gheorghiu")]
 #[command(about = "A simple CLI tool to extract files from a minifs binary.")]
 #[command(version)]
 #[command(
     help_template = "{about-section}{author}\nVersion: {version} \n {usage-heading} {usage} \n {all-args} {tab}"
 )]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    /// The binary file containing the minifs filesystem
-    binary: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The binary files containing the minifs filesystems, extracted when no command is given
+    #[arg(required_unless_present = "version_info")]
+    binaries: Vec<String>,
+
+    #[command(flatten)]
+    parse: ParseOptions,
+
+    #[command(flatten)]
+    extract: ExtractOptions,
+
+    /// Print the minifs format variants and codecs supported by this build
+    #[arg(long, exclusive = true)]
+    version_info: bool,
+
+    /// Print the parsed command line options before running
+    #[arg(long, global = true)]
+    show_config: bool,
+
+    /// Color the status lines
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Fail on any warning, such as skipped files, unexpected sizes or extra data, so only images
+    /// which extract cleanly succeed. Overrides --tolerate-size-mismatch.
+    #[arg(long, global = true)]
+    strict: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Extract all files from the minifs (default)
+    Extract {
+        /// The binary files containing the minifs filesystems
+        #[arg(required = true)]
+        binaries: Vec<String>,
+
+        #[command(flatten)]
+        parse: ParseOptions,
+
+        #[command(flatten)]
+        options: Box<ExtractOptions>,
+    },
+    /// List the files in the minifs without extracting them
+    List {
+        /// The binary file containing the minifs filesystem
+        binary: String,
+
+        #[command(flatten)]
+        parse: ParseOptions,
+
+        #[command(flatten)]
+        options: ListOptions,
+    },
+    /// Print the layout of the minifs header and tables
+    Info {
+        /// The binary file containing the minifs filesystem
+        binary: String,
+
+        #[command(flatten)]
+        parse: ParseOptions,
+
+        #[command(flatten)]
+        options: InfoOptions,
+    },
+    /// Decompress every chunk and check it matches the size declared in the Table of Chunks
+    Verify {
+        /// The binary file containing the minifs filesystem
+        binary: String,
+
+        #[command(flatten)]
+        parse: ParseOptions,
+    },
+    /// Mount the minifs read-only with FUSE, decompressing files as they are read, until unmounted
+    #[cfg(feature = "mount")]
+    Mount {
+        /// The binary file containing the minifs filesystem
+        binary: String,
+
+        /// The directory to mount the minifs on
+        mountpoint: PathBuf,
+
+        #[command(flatten)]
+        parse: ParseOptions,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ParseOptions {
+    /// Size of the minifs header, where the Table of Names starts, in decimal or 0x-prefixed hex
+    #[arg(long, value_name = "N", value_parser = parse_number, default_value = "32")]
+    header_size: usize,
+
+    /// Read the Table of Names offset from the header field at HEX, for images storing the names
+    /// after the chunks
+    #[arg(long, value_name = "HEX", value_parser = parse_hex)]
+    ton_offset_field: Option<usize>,
+
+    /// Parse images whose chunks don't use the LZMA codec, to inspect their tables. Nothing can be
+    /// decompressed from them.
+    #[arg(long)]
+    no_codec_check: bool,
+
+    /// Read the chunk offsets as relative to the minifs header instead of the raw chunks
+    #[arg(long)]
+    absolute_chunk_offsets: bool,
+
+    /// Reject images whose names aren't NUL-terminated within N bytes
+    #[arg(long, value_name = "N", default_value_t = 4096)]
+    null_string_scan_limit: usize,
+
+    /// Offset of the number of files in the header
+    #[arg(long, value_name = "HEX", value_parser = parse_hex, default_value = "0x14")]
+    files_no_offset: usize,
+
+    /// Offset of the size of the Table of Names in the header
+    #[arg(long, value_name = "HEX", value_parser = parse_hex, default_value = "0x1c")]
+    ton_size_offset: usize,
+
+    /// Recover what can be sliced from chunks decompressing to less than their declared size
+    /// instead of failing, warning about the truncated and skipped files
+    #[arg(long)]
+    tolerate_size_mismatch: bool,
+
+    /// Skip the per-entry checks of the tables in release builds, for known good images: name
+    /// offsets and terminators, overlapping chunks and files starting past their chunk
+    #[arg(long)]
+    trust_input: bool,
+
+    /// Read the name shared by the path and filename of an entry as its full path, for images
+    /// storing a single string per file
+    #[arg(long)]
+    combined_names: bool,
+
+    /// Only look for the minifs header within the first BYTES of the binary
+    #[arg(long, value_name = "BYTES")]
+    header_search_window: Option<usize>,
+
+    /// Count the chunks from where the Table of Chunks ends instead of from the last file, for
+    /// images whose files aren't ordered by chunk
+    #[arg(long)]
+    infer_chunks: bool,
+}
+
+impl ParseOptions {
+    fn to_minifs_options(&self) -> MiniFsOptions {
+        let options = MiniFsOptions::new()
+            .header_size(self.header_size)
+            .no_codec_check(self.no_codec_check)
+            .absolute_chunk_offsets(self.absolute_chunk_offsets)
+            .name_scan_limit(self.null_string_scan_limit)
+            .files_no_offset(self.files_no_offset)
+            .ton_size_offset(self.ton_size_offset)
+            .tolerate_size_mismatch(self.tolerate_size_mismatch && !STRICT.load(Ordering::Relaxed))
+            .trust_input(self.trust_input)
+            .combined_names(self.combined_names)
+            .infer_chunks(self.infer_chunks);
+        let options = match self.ton_offset_field {
+            Some(ton_offset_field) => options.ton_offset_field(ton_offset_field),
+            None => options,
+        };
+        match self.header_search_window {
+            Some(header_search_window) => options.header_search_window(header_search_window),
+            None => options,
+        }
+    }
+}
+
+fn parse_hex(value: &str) -> Result<usize, std::num::ParseIntError> {
+    usize::from_str_radix(value.trim_start_matches("0x"), 16)
+}
+
+/// Parses a decimal number, or a hexadecimal one with a `0x` prefix
+fn parse_number(value: &str) -> Result<usize, std::num::ParseIntError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+}
+
+fn parse_extract_to(value: &str) -> Result<(String, PathBuf), String> {
+    match value.split_once('=') {
+        Some((internal, output)) if !internal.is_empty() && !output.is_empty() => {
+            Ok((internal.to_string(), PathBuf::from(output)))
+        }
+        _ => Err("expected INTERNAL_PATH=OUTPUT_PATH".to_string()),
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct ExtractOptions {
+    /// Also write a mapping of TOF index to path/filename into FILE
+    #[arg(long, value_name = "FILE")]
+    index_map: Option<String>,
+
+    /// Hardlink files identical to an already extracted one instead of writing them again
+    #[arg(long)]
+    dedup: bool,
+
+    /// Convert CRLF line endings to LF in the files which look like text, binaries are written
+    /// unchanged
+    #[arg(long)]
+    text_mode: bool,
+
+    /// Percent-encode filenames containing control characters, recording the original names
+    /// into `<output directory>.names`
+    #[arg(long)]
+    encode_names: bool,
+
+    /// Rewrite extracted paths using the `from<TAB>to` prefix lines of FILE, longest prefix first
+    #[arg(long, value_name = "FILE")]
+    rename_map: Option<String>,
+
+    /// Skip files which already exist in the output directory with the expected size, under the
+    /// name and with the contents they would be written with
+    #[arg(long)]
+    resume: bool,
+
+    /// Print each extracted file path terminated by a NUL byte, for use with `xargs -0`
+    #[arg(long)]
+    print0: bool,
+
+    /// Only extract the first N files, in Table of Files order
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// Prepend PREFIX to the output directory name, to separate images sharing a file name
+    #[arg(long, value_name = "PREFIX")]
+    output_prefix: Option<String>,
+
+    /// Show the extraction progress, weighted by the size of the files
+    #[arg(long)]
+    progress: bool,
+
+    /// If a file can't be created, retry with characters illegal on common filesystems replaced by `_`
+    #[arg(long)]
+    sanitize: bool,
+
+    /// Write all files concatenated into FILE, with their offsets in FILE.manifest, instead of
+    /// extracting them into a directory
+    #[arg(long, value_name = "FILE")]
+    pack: Option<String>,
+
+    /// Write the SHA-256 of every file into FILE as sorted `path/filename<TAB>sha256` lines instead
+    /// of extracting them
+    #[arg(long, value_name = "FILE")]
+    hashes_only: Option<String>,
+
+    /// Run the shell command CMD for every file with its data on stdin and its path/filename in
+    /// $MINIFS_PATH, instead of extracting them
+    #[arg(long, value_name = "CMD")]
+    pipe_each: Option<String>,
+
+    /// Number of --pipe-each commands running at once
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+
+    /// Only extract the files whose path/filename matches GLOB, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    filter: Vec<Glob>,
+
+    /// Skip the files whose path/filename matches GLOB, applied after --filter, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<Glob>,
+
+    /// Skip the files stored in chunk N without decompressing it, to recover the rest of an image
+    /// with a corrupt chunk, can be repeated
+    #[arg(long, value_name = "N")]
+    exclude_chunk: Vec<u32>,
+
+    /// Write the contents of the file at PATH to stdout instead of extracting
+    #[arg(long, value_name = "PATH")]
+    cat: Option<String>,
+
+    /// Write the file at INTERNAL_PATH to exactly OUTPUT_PATH instead of extracting everything,
+    /// can be repeated
+    #[arg(long, value_name = "INTERNAL_PATH=OUTPUT_PATH", value_parser = parse_extract_to)]
+    extract_to: Vec<(String, PathBuf)>,
+
+    /// Write the whole decompressed chunk N into _<binary>_chunk_N.bin instead of extracting, to
+    /// see how the files are packed in it
+    #[arg(long, value_name = "N")]
+    extract_chunk: Option<usize>,
+
+    /// Write an index of the chunks and files into FILE instead of extracting, for later use with
+    /// --from-index
+    #[arg(long, value_name = "FILE")]
+    index_only: Option<String>,
+
+    /// Read the chunk and file locations from an index written by --index-only instead of
+    /// parsing the binary
+    #[arg(long, value_name = "FILE", requires = "cat")]
+    from_index: Option<String>,
+
+    /// Match --filter, --exclude and --cat paths case-insensitively
+    #[arg(long)]
+    ignore_case: bool,
+
+    /// Don't create any directories, fail if a file's directory doesn't already exist
+    #[arg(long)]
+    no_create_dirs: bool,
+
+    /// Name the output directory after the SHA-256 of the binary instead of its file name
+    #[arg(long)]
+    hash_output: bool,
+
+    /// Only decompress the chunks and check their sizes, without extracting any file
+    #[arg(long)]
+    decompress_only: bool,
+
+    /// Print how long each chunk took to decompress
+    #[arg(long)]
+    timing: bool,
+
+    /// Skip files whose path has more than N components
+    #[arg(long, value_name = "N", default_value_t = 64)]
+    max_path_depth: usize,
+
+    /// Extract into a temporary directory and move it into place once complete, so concurrent runs
+    /// never leave interleaved partial trees. The output directory must not exist yet.
+    #[arg(long, conflicts_with_all = ["resume", "no_create_dirs"])]
+    staged: bool,
+
+    /// Print the offset of every minifs magic in the binary and whether its header looks valid,
+    /// without extracting anything
+    #[arg(long)]
+    print_magic_offsets: bool,
+
+    /// Keep watching the binary and extract the minifs images appended to it, until interrupted.
+    /// Only the parse options and --max-path-depth apply to the appended images.
+    #[arg(long)]
+    follow: bool,
+
+    /// Compare the decompressed files against an existing extraction in DIR instead of writing them
+    #[arg(long, value_name = "DIR")]
+    compare_to_dir: Option<String>,
+
+    /// Write the minifs image alone, from its header to the end of its final chunk, into FILE
+    /// instead of extracting
+    #[arg(long, value_name = "FILE")]
+    carve: Option<String>,
+
+    /// Also write a `mksquashfs` pseudo file into FILE giving every extracted path root ownership,
+    /// to rebuild an image with `mksquashfs -pf FILE`
+    #[arg(long, value_name = "FILE")]
+    fakeroot_manifest: Option<String>,
+
+    /// Only extract the last entry in Table of Files order of the paths stored several times, such
+    /// as the A/B slots of updates
+    #[arg(long)]
+    extract_newest_only: bool,
+
+    /// Log each file into FILE as it is written, as `index<TAB>path` lines, to compare the
+    /// extraction order across runs
+    #[arg(long, value_name = "FILE")]
+    order_log: Option<String>,
+
+    /// Also write the paths, sizes and SHA-256 of the extracted files into FILE as JSON lines, for
+    /// later use with --changed-since
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<String>,
+
+    /// Only extract the files whose size or SHA-256 differs from the ones in MANIFEST, written by
+    /// --manifest. The sizes alone are compared with a `list --jsonl` output.
+    #[arg(long, value_name = "MANIFEST")]
+    changed_since: Option<String>,
+
+    /// Print a single digest of all the extracted paths and contents
+    #[arg(long)]
+    merkle: bool,
+}
+
+impl ExtractOptions {
+    /// The given flags writing to a single path, or never returning, which can't be shared by
+    /// several binaries
+    fn single_output_flags(&self) -> Vec<&'static str> {
+        [
+            ("--pack", self.pack.is_some()),
+            ("--index-map", self.index_map.is_some()),
+            ("--manifest", self.manifest.is_some()),
+            ("--hashes-only", self.hashes_only.is_some()),
+            ("--order-log", self.order_log.is_some()),
+            ("--index-only", self.index_only.is_some()),
+            ("--carve", self.carve.is_some()),
+            ("--fakeroot-manifest", self.fakeroot_manifest.is_some()),
+            ("--extract-to", !self.extract_to.is_empty()),
+            ("--follow", self.follow),
+        ]
+        .into_iter()
+        .filter(|(_, given)| *given)
+        .map(|(flag, _)| flag)
+        .collect()
+    }
+
+    /// Whether stdout is reserved for the extracted paths or data
+    fn reserves_stdout(&self) -> bool {
+        self.print0 || self.cat.is_some()
+    }
+}
+
+/// A glob pattern which is printed the way it was written
+#[derive(Clone)]
+struct Glob(Pattern);
+
+impl FromStr for Glob {
+    type Err = glob::PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::new(s).map(Glob)
+    }
+}
+
+impl Deref for Glob {
+    type Target = Pattern;
+
+    fn deref(&self) -> &Pattern {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0.as_str())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct ListOptions {
+    /// Write the listing as CSV into FILE
+    #[arg(long, value_name = "FILE")]
+    csv: Option<String>,
+
+    /// Print the listing as one JSON object per line on stdout
+    #[arg(long)]
+    jsonl: bool,
+
+    /// Write the listing into a `files` table of the SQLite database FILE, appending to an existing
+    /// table
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "FILE")]
+    sqlite: Option<String>,
+
+    /// Only list the directories, with the number of files in each
+    #[arg(long)]
+    list_dirs: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct InfoOptions {
+    /// Save the bytes preceding the minifs header into _<binary>_prefix.bin
+    #[arg(long)]
+    save_prefix: bool,
+
+    /// Dump the raw TON, TOF and TOC tables into _<binary>.tables
+    #[arg(long)]
+    dump_tables: bool,
+
+    /// Report the files whose data overlap within a shared chunk
+    #[arg(long)]
+    check_overlaps: bool,
+
+    /// Check that each chunk starts where the previous one ends in the raw chunks, reporting the
+    /// gaps, overlaps and chunks out of order
+    #[arg(long)]
+    check_toc: bool,
 }
 
 fn main() {
-    let args = Args::parse();
-    let path = Path::new(&args.binary);
-    let original_file_name = path.file_name().unwrap().to_string_lossy();
-    let output_dir = format!("_{}.extracted", original_file_name);
+    let matches = Args::command().get_matches();
+    reject_options_before_command(&matches);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    COLOR.set(args.color).unwrap();
+    STRICT.store(args.strict, Ordering::Relaxed);
+    if args.version_info {
+        print_version_info();
+        return;
+    }
+
+    let reserves_stdout = match &args.command {
+        None => args.extract.reserves_stdout(),
+        Some(Command::Extract { options, .. }) => options.reserves_stdout(),
+        Some(Command::List { options, .. }) => options.jsonl,
+        _ => false,
+    };
+    STATUS_TO_STDERR.store(reserves_stdout, Ordering::Relaxed);
+    SHOW_CONFIG.store(args.show_config, Ordering::Relaxed);
+    if args.show_config {
+        status!("[+] Configuration: {:#?}", args);
+        status!(
+            "[+] Codecs: lzma (configuration word {:#010X})",
+            minifs_extractor::LZMA_CONFIGURATION_WORD
+        );
+    }
+
+    match args.command {
+        // clap guarantees a binary is present unless --version-info was given
+        None => extract_all(&args.binaries, &args.parse, args.extract),
+        Some(Command::Extract {
+            binaries,
+            parse,
+            options,
+        }) => extract_all(&binaries, &parse, *options),
+        Some(Command::List {
+            binary,
+            parse,
+            options,
+        }) => list(&binary, &parse, options),
+        Some(Command::Info {
+            binary,
+            parse,
+            options,
+        }) => info(&binary, &parse, options),
+        Some(Command::Verify { binary, parse }) => verify(&binary, &parse),
+        #[cfg(feature = "mount")]
+        Some(Command::Mount {
+            binary,
+            mountpoint,
+            parse,
+        }) => mount_binary(&binary, &mountpoint, &parse),
+    }
+    check_strict();
+}
+
+/// The top-level options only apply without a command, the command would silently ignore them
+fn reject_options_before_command(matches: &ArgMatches) {
+    if matches.subcommand().is_none() {
+        return;
+    }
+
+    let command = Args::command();
+    let given = command
+        .get_arguments()
+        .filter(|x| !x.is_global_set())
+        .filter(|x| matches.value_source(x.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .map(|x| match x.get_long() {
+            Some(long) => format!("--{}", long),
+            None => x.get_id().to_string(),
+        })
+        .collect::<Vec<String>>();
+    if !given.is_empty() {
+        command
+            .clone()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "{} must be given after the command to apply to it",
+                    given.join(", ")
+                ),
+            )
+            .exit();
+    }
+}
+
+/// Whether --strict was given and a warning was printed for the current binary
+fn strict_failed() -> bool {
+    STRICT.load(Ordering::Relaxed) && WARNINGS.load(Ordering::Relaxed) > 0
+}
+
+/// Fails if --strict was given and a warning was printed for the current binary. Called once each
+/// step is done, so nothing is left half written.
+fn check_strict() {
+    if strict_failed() {
+        status!(
+            "[-] Failing on {} warnings with --strict",
+            WARNINGS.load(Ordering::Relaxed)
+        );
+        fail();
+    }
+}
+
+/// Aborts after an error was reported. When extracting several binaries, only the current one is
+/// aborted.
+fn fail() -> ! {
+    if BATCH_MODE.load(Ordering::Relaxed) {
+        // Unwinds to `extract_all` without running the panic hook
+        std::panic::resume_unwind(Box::new(()));
+    }
+    exit(1)
+}
+
+/// Extracts each binary into its own directory. A binary failing doesn't stop the others, they
+/// are summarized at the end.
+fn extract_all(binaries: &[String], parse: &ParseOptions, args: ExtractOptions) {
+    if let [binary] = binaries {
+        return extract(binary, parse, args);
+    }
+
+    let flags = args.single_output_flags();
+    if !flags.is_empty() {
+        status!(
+            "[-] {} can only be used with a single binary",
+            flags.join(", ")
+        );
+        exit(1);
+    }
+
+    BATCH_MODE.store(true, Ordering::Relaxed);
+    let failed = binaries
+        .iter()
+        .filter(|binary| {
+            status!("[+] Extracting {}", binary);
+            let args = args.clone();
+            WARNINGS.store(0, Ordering::Relaxed);
+            std::panic::catch_unwind(AssertUnwindSafe(|| {
+                extract(binary, parse, args);
+                check_strict();
+            }))
+            .is_err()
+        })
+        .collect::<Vec<&String>>();
+
+    status!(
+        "[+] Extracted {} of {} binaries",
+        binaries.len() - failed.len(),
+        binaries.len()
+    );
+    for binary in failed.iter() {
+        status!("[-] Failed to extract {}", binary);
+    }
+    if !failed.is_empty() {
+        exit(1);
+    }
+}
 
-    let mut fd = File::open(path).expect("File not found");
+/// Seekable source of a binary, to read it again after it was parsed
+trait Input: Read + Seek {}
+
+impl<T: Read + Seek> Input for T {}
+
+fn is_url(binary: &str) -> bool {
+    binary.starts_with("http://") || binary.starts_with("https://")
+}
+
+/// Reads the whole contents of `binary`, which may be an HTTP(S) URL with the `http` feature
+fn read_binary(binary: &str) -> (Vec<u8>, Box<dyn Input>) {
+    if is_url(binary) {
+        return fetch(binary);
+    }
+
+    let mut fd = File::open(binary).expect("File not found");
     let mut content: Vec<u8> = Vec::new();
     fd.read_to_end(&mut content).expect("Unsupported file");
+    (content, Box::new(fd))
+}
 
-    match MiniFs::parse(content) {
+#[cfg(feature = "http")]
+fn fetch(url: &str) -> (Vec<u8>, Box<dyn Input>) {
+    let content = reqwest::blocking::get(url)
+        .and_then(|x| x.error_for_status())
+        .and_then(|x| x.bytes())
+        .unwrap_or_else(|e| {
+            status!("[-] Couldn't download {}: {}", url, e);
+            fail();
+        })
+        .to_vec();
+    status!("[+] Downloaded {} bytes from {}", content.len(), url);
+    // Parsing consumes the contents, the copy is what gets read again
+    (content.clone(), Box::new(std::io::Cursor::new(content)))
+}
+
+#[cfg(not(feature = "http"))]
+fn fetch(url: &str) -> (Vec<u8>, Box<dyn Input>) {
+    status!(
+        "[-] Reading {} requires building with the http feature",
+        url
+    );
+    fail();
+}
+
+/// Reads and parses `binary`, exiting if it doesn't contain a valid minifs
+fn open(binary: &str, options: &MiniFsOptions) -> (MiniFs, Box<dyn Input>) {
+    let (content, fd) = read_binary(binary);
+
+    match MiniFs::parse_with_options(content, options) {
         Err(e) => {
-            match e {
-                ParseError::InvalidHeader => {
-                    println!("[-] Invalid minifs header");
-                }
-                ParseError::UnsupportedVersion => {
-                    println!("[-] Unsupported minifs version");
-                }
-            };
-            exit(1);
+            status!("[-] {}", e);
+            fail();
         }
-        Ok(minifs) => {
-            println!(
+        Ok(Parsed { minifs, warnings }) => {
+            status!(
                 "[+] Found minifs header at {:#x}",
                 minifs.get_header_start()
             );
-            println!(
-                "[+] Found {} files in minifs. Extracing...",
-                minifs.get_files_no()
+            status!("[+] Using {} byte order", minifs.get_endianness());
+            for warning in warnings {
+                match warning {
+                    ParseWarning::SkippedPadding(_) => status!("[+] {}", warning),
+                    ParseWarning::TrailingData(bytes)
+                        if bytes <= TRAILING_DATA_TOLERANCE && !STRICT.load(Ordering::Relaxed) => {}
+                    _ => warning!("{}", warning),
+                }
+            }
+            check_strict();
+
+            (minifs, fd)
+        }
+    }
+}
+
+/// File name of the binary, used to name the files and directories created next to it. For URLs,
+/// this is the last segment of their path.
+fn binary_name(binary: &str) -> String {
+    if is_url(binary) {
+        let path = binary.split(['?', '#']).next().unwrap_or_default();
+        return path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+    }
+
+    Path::new(binary)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn extract(binary: &str, parse: &ParseOptions, args: ExtractOptions) {
+    if args.print_magic_offsets {
+        print_magic_offsets(binary);
+        return;
+    }
+
+    if let Some(index) = args.from_index {
+        // clap guarantees --cat is present with --from-index
+        cat_from_index(binary, &index, &args.cat.unwrap(), args.ignore_case);
+        return;
+    }
+
+    let (minifs, mut fd) = open(binary, &parse.to_minifs_options());
+    if let Some(carve) = args.carve {
+        let image = minifs.image_bytes();
+        std::fs::write(&carve, image).expect("Couldn't write carved image");
+        status!(
+            "[+] Carved {} bytes at offset {:#x} into {}",
+            image.len(),
+            minifs.get_header_start(),
+            carve
+        );
+        return;
+    }
+    require_codec(&minifs);
+
+    let output_name = if args.hash_output {
+        fd.seek(SeekFrom::Start(0))
+            .and_then(|_| hash::sha256_reader(&mut fd))
+            .expect("Couldn't hash input file")[..HASH_OUTPUT_PREFIX_LEN]
+            .to_string()
+    } else {
+        binary_name(binary)
+    };
+    let output_stem = format!(
+        "{}_{}",
+        args.output_prefix.as_deref().unwrap_or_default(),
+        output_name
+    );
+    let output_dir = format!("{output_stem}.extracted");
+    if SHOW_CONFIG.load(Ordering::Relaxed) {
+        status!(
+            "[+] Resolved configuration: output directory {}, {} byte order",
+            output_dir,
+            minifs.get_endianness()
+        );
+    }
+
+    if let Some(index) = args.index_only {
+        export::write_index(&index, &minifs).expect("Couldn't write index");
+        status!(
+            "[+] Wrote the index of {} files into {}",
+            minifs.get_files_no(),
+            index
+        );
+        return;
+    }
+
+    if let Some(cat) = args.cat {
+        let Some(file) = minifs.find(&cat, args.ignore_case) else {
+            status!("[-] {} not found in minifs", cat);
+            fail();
+        };
+        std::io::stdout()
+            .write_all(&extract_file(&minifs, &file).data)
+            .expect("Couldn't write to stdout");
+        return;
+    }
+
+    if !args.extract_to.is_empty() {
+        for (internal, output) in args.extract_to.iter() {
+            let Some(file) = minifs.find(internal, args.ignore_case) else {
+                status!("[-] {} not found in minifs", internal);
+                fail();
+            };
+            let extracted = extract_file(&minifs, &file);
+            write_atomic(output, extracted.data.chunks(WRITE_PIECE_SIZE))
+                .expect("Couldn't write to file");
+            status!("[+] {} -> {}", file.full_path(), output.to_string_lossy());
+        }
+        return;
+    }
+
+    if let Some(chunk_number) = args.extract_chunk {
+        let mut warnings = Vec::new();
+        let Some(data) = minifs.decompressed_chunk(chunk_number, &mut warnings) else {
+            status!(
+                "[-] There is no chunk {}, the minifs has {} chunks",
+                chunk_number,
+                minifs.get_chunks_no()
             );
+            fail();
+        };
+        let data = data.unwrap_or_else(|e| {
+            status!("[-] {}", e);
+            fail();
+        });
+        print_warnings(&warnings);
+        let chunk_file = format!("_{}_chunk_{}.bin", binary_name(binary), chunk_number);
+        std::fs::write(&chunk_file, &data).expect("Couldn't write chunk file");
+        status!(
+            "[+] Wrote the {} bytes of chunk {} into {}",
+            data.len(),
+            chunk_number,
+            chunk_file
+        );
+        return;
+    }
+
+    if args.decompress_only {
+        let (total, warnings) = minifs.decompress_all().unwrap_or_else(|e| {
+            status!("[-] {}", e);
+            fail();
+        });
+        print_warnings(&warnings);
+        status!(
+            "[+] Decompressed {} chunks into {} bytes, all chunks match their declared size",
+            minifs.get_chunks_no(),
+            total
+        );
+        return;
+    }
+
+    // The staged tree can only be renamed onto a directory which doesn't exist
+    if args.staged && Path::new(&output_dir).exists() {
+        status!(
+            "[-] {} already exists, remove it to extract with --staged",
+            output_dir
+        );
+        fail();
+    }
 
-            let files = minifs.extract();
-            std::fs::create_dir_all(output_dir.clone()).expect("Couldn't create output directory");
-            for file in files.into_iter() {
-                let path = PathBuf::from_str(&format!("{output_dir}/{}", file.path))
-                    .expect("Invalid path");
-                let mut file_path = path.clone();
-                file_path.push(file.filename);
+    let rename_map = args
+        .rename_map
+        .as_deref()
+        .map(read_rename_map)
+        .unwrap_or_default();
 
-                if file_path.components().any(|x| x == Component::ParentDir)
-                    || file_path.starts_with("/")
+    let baseline = args.changed_since.as_deref().map(read_manifest);
+
+    let match_options = MatchOptions {
+        case_sensitive: !args.ignore_case,
+        ..Default::default()
+    };
+
+    status!(
+        "[+] Found {} files in minifs. Extracing...",
+        minifs.get_files_no()
+    );
+    status!(
+        "[+] Total size is {} bytes ({} bytes compressed)",
+        minifs.total_size(),
+        minifs.total_compressed_size()
+    );
+
+    for file in minifs.list() {
+        let chunks = minifs.chunks_spanned(file.entry);
+        if chunks.len() > 1 {
+            warning!(
+                "{} spans chunks {} to {}",
+                file.full_path(),
+                chunks.start,
+                chunks.end - 1
+            );
+        }
+    }
+
+    // Later entries in TOF order win over the earlier versions of the same path
+    let newest: HashMap<String, usize> = if args.extract_newest_only {
+        minifs
+            .list()
+            .iter()
+            .map(|x| (x.full_path(), x.index))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+
+    // Where each file ends up under the output directory
+    let output_names = |path: &str, filename: &str| {
+        let filename = if args.encode_names && filename.contains(char::is_control) {
+            encode_filename(filename)
+        } else {
+            filename.to_string()
+        };
+        (rename_path(&rename_map, path), filename)
+    };
+
+    let predicate = |x: &FileInfo| {
+        if args.limit.is_some_and(|limit| x.index >= limit) {
+            return false;
+        }
+
+        if newest
+            .get(&x.full_path())
+            .is_some_and(|index| *index != x.index)
+        {
+            status!(
+                "[+] Skipping {}, a later entry has the same path",
+                x.full_path()
+            );
+            return false;
+        }
+
+        if !args.filter.is_empty()
+            && !args
+                .filter
+                .iter()
+                .any(|pattern| pattern.matches_with(&x.full_path(), match_options))
+        {
+            return false;
+        }
+
+        if args
+            .exclude
+            .iter()
+            .any(|pattern| pattern.matches_with(&x.full_path(), match_options))
+        {
+            return false;
+        }
+
+        if minifs
+            .chunks_spanned(x.entry)
+            .any(|chunk_number| args.exclude_chunk.contains(&chunk_number))
+        {
+            warning!(
+                "Skipping {}, it is stored in an excluded chunk",
+                x.full_path()
+            );
+            return false;
+        }
+
+        // Text files converted by --text-mode are only compared once decompressed
+        let (path, filename) = output_names(&x.path, &x.filename);
+        if args.resume
+            && extracted_size(&output_dir, &path, &filename, args.sanitize)
+                == Some(x.entry.file_size as u64)
+        {
+            status!("[+] Skipping {}", x.full_path());
+            return false;
+        }
+
+        // Files with the same size and a digest in the baseline are compared once decompressed
+        let baseline_entry = baseline.as_ref().and_then(|y| y.get(&x.full_path()));
+        if let Some((size, None)) = baseline_entry {
+            if *size == x.entry.file_size as u64 {
+                status!(
+                    "[+] Skipping {}, unchanged since the baseline",
+                    x.full_path()
+                );
+                return false;
+            }
+        }
+
+        true
+    };
+    // The predicate reports the files it skips, so it only runs once
+    let selected = minifs
+        .iter_files()
+        .filter(|x| predicate(x))
+        .collect::<Vec<FileInfo>>();
+    let selected_indices = selected.iter().map(|x| x.index).collect::<HashSet<usize>>();
+    // Warnings about the selection fail before anything is decompressed
+    check_strict();
+
+    // The digests of the baseline can only be compared once the files are decompressed
+    let is_unchanged =
+        |x: &DecompressedFile| match baseline.as_ref().and_then(|y| y.get(&x.full_path())) {
+            Some((size, Some(digest)))
+                if *size == x.data.len() as u64 && *digest == hash::sha256_hex(&x.data) =>
+            {
+                status!(
+                    "[+] Skipping {}, unchanged since the baseline",
+                    x.full_path()
+                );
+                true
+            }
+            _ => false,
+        };
+    let chunks = minifs.extract_by_chunk_where(|x| selected_indices.contains(&x.index));
+    let mut timings = Vec::new();
+
+    // These need all the files at once, everything else gets each file written and dropped as
+    // soon as its chunk is decompressed
+    if args.pack.is_some()
+        || args.pipe_each.is_some()
+        || args.hashes_only.is_some()
+        || args.compare_to_dir.is_some()
+    {
+        let mut files = Vec::new();
+        for (_, extracted) in chunks {
+            let extracted = extracted.unwrap_or_else(|e| {
+                status!("[-] {}", e);
+                fail();
+            });
+            print_warnings(&extracted.warnings);
+            timings.extend(extracted.timings);
+            files.extend(extracted.files.into_iter().filter(|x| !is_unchanged(x)));
+        }
+        check_strict();
+        status!("[+] Decompressed {} chunks", timings.len());
+        if args.timing {
+            print_timings(&timings);
+        }
+
+        if let Some(pack) = args.pack {
+            export::write_pack(&pack, &files).expect("Couldn't write pack file");
+            status!("[+] Packed {} files into {}", files.len(), pack);
+        } else if let Some(command) = args.pipe_each {
+            pipe_each(&command, &files, args.threads as usize);
+        } else if let Some(hashes) = args.hashes_only {
+            export::write_hashes(&hashes, &files).expect("Couldn't write hashes file");
+            status!(
+                "[+] Wrote the hashes of {} files into {}",
+                files.len(),
+                hashes
+            );
+        } else if let Some(compare_dir) = args.compare_to_dir {
+            compare_to_dir(&compare_dir, &files, &rename_map);
+        }
+        return;
+    }
+
+    let total_bytes: u64 = selected.iter().map(|x| x.entry.file_size as u64).sum();
+    let mut written_bytes = 0_u64;
+
+    // With --staged the tree is built next to the output directory and moved into place at the end
+    let final_dir = output_dir;
+    let output_dir = if args.staged {
+        format!("{}.{}.tmp", final_dir, std::process::id())
+    } else {
+        final_dir.clone()
+    };
+
+    let mut order_log = args
+        .order_log
+        .as_ref()
+        .map(|x| BufWriter::new(File::create(x).expect("Couldn't create order log")));
+    let mut manifest = args
+        .manifest
+        .as_ref()
+        .map(|x| BufWriter::new(File::create(x).expect("Couldn't create manifest")));
+    let mut manifest_entries = 0;
+    let mut merkle = args.merkle.then(hash::MerkleTree::default);
+    let mut written_files = Vec::new();
+    let mut encoded_names = Vec::new();
+    let mut first_copies: HashMap<String, PathBuf> = HashMap::new();
+    if args.no_create_dirs {
+        require_dir(Path::new(&output_dir));
+    } else {
+        std::fs::create_dir_all(output_dir.clone()).expect("Couldn't create output directory");
+    }
+
+    // Paths are rewritten before being checked, the original filenames go into the names file
+    let original_names = selected
+        .iter()
+        .filter(|x| args.encode_names && x.filename.contains(char::is_control))
+        .map(|x| (x.index, x.filename.clone()))
+        .collect::<HashMap<usize, String>>();
+
+    let options = ExtractionOptions {
+        output_dir: PathBuf::from(&output_dir),
+        max_path_depth: args.max_path_depth,
+        no_create_dirs: args.no_create_dirs,
+    };
+    // The directories of files which are likely unchanged since the baseline are only created if
+    // they did change
+    let names = selected
+        .iter()
+        .filter(|x| {
+            !matches!(
+                baseline.as_ref().and_then(|y| y.get(&x.full_path())),
+                Some((size, Some(_))) if *size == x.entry.file_size as u64
+            )
+        })
+        .map(|x| output_names(&x.path, &x.filename))
+        .collect::<Vec<(String, String)>>();
+    let mut tree = OutputTree::new(
+        &options,
+        names
+            .iter()
+            .map(|(path, filename)| (path.as_str(), filename.as_str())),
+    );
+    let mut write = |file: &DecompressedFile, file_path: &Path| {
+        if args.progress {
+            written_bytes += file.data.len() as u64;
+            status!(
+                "[+] {:>3}% {}",
+                (written_bytes * 100)
+                    .checked_div(total_bytes)
+                    .unwrap_or(100),
+                file_path.to_string_lossy()
+            );
+        } else {
+            status!("[+] {}", file_path.to_string_lossy());
+        }
+
+        let data = if args.text_mode && is_text(&file.data) {
+            Cow::Owned(crlf_to_lf(&file.data))
+        } else {
+            Cow::Borrowed(&file.data[..])
+        };
+        // With --dedup, files identical to one already written are hardlinked to it
+        let digest = args.dedup.then(|| hash::sha256_hex(&data));
+        let first_copy = digest.as_ref().and_then(|x| first_copies.get(x));
+        let file_path = match first_copy {
+            Some(first_copy) if std::fs::hard_link(first_copy, file_path).is_ok() => {
+                status!(
+                    "[+] Linked {} to {}",
+                    file_path.to_string_lossy(),
+                    first_copy.to_string_lossy()
+                );
+                file_path.to_path_buf()
+            }
+            _ => match write_atomic(file_path, data.chunks(WRITE_PIECE_SIZE)) {
+                Err(_) if args.sanitize && sanitize_filename(&file.filename) != file.filename => {
+                    let sanitized_path =
+                        file_path.with_file_name(sanitize_filename(&file.filename));
+                    warning!(
+                        "Renamed {} to {}",
+                        file_path.to_string_lossy(),
+                        sanitized_path.to_string_lossy()
+                    );
+                    write_atomic(&sanitized_path, data.chunks(WRITE_PIECE_SIZE))?;
+                    sanitized_path
+                }
+                result => result.map(|_| file_path.to_path_buf())?,
+            },
+        };
+        if let Some(digest) = digest {
+            first_copies
+                .entry(digest)
+                .or_insert_with(|| file_path.clone());
+        }
+        if let Some(original) = original_names.get(&file.index) {
+            let relative_path = file_path.strip_prefix(&output_dir).unwrap().to_path_buf();
+            encoded_names.push((relative_path, original.clone()));
+        }
+        if let Some(order_log) = order_log.as_mut() {
+            // With --staged, the path the file ends up at once the tree is moved into place
+            let final_path =
+                Path::new(&final_dir).join(file_path.strip_prefix(&output_dir).unwrap());
+            writeln!(
+                order_log,
+                "{}\t{}",
+                file.index,
+                final_path.to_string_lossy()
+            )
+            .expect("Couldn't write order log");
+        }
+        written_files.push(file_path);
+        Ok(())
+    };
+
+    for (chunk_number, extracted) in chunks {
+        let extracted = match extracted {
+            Ok(extracted) => extracted,
+            Err(e) => {
+                for x in selected
+                    .iter()
+                    .filter(|x| x.entry.chunk_number == chunk_number)
                 {
-                    panic!("This is not dangerous");
+                    tree.fail(x.full_path(), ExtractionProblem::Failed(e.to_string()));
                 }
+                continue;
+            }
+        };
+        tree.warn(extracted.warnings);
+        timings.extend(extracted.timings);
 
-                println!("[+] {}", file_path.clone().to_string_lossy());
-                let _ = std::fs::create_dir_all(path);
+        for mut file in extracted.files.into_iter().filter(|x| !is_unchanged(x)) {
+            let (path, filename) = output_names(&file.path, &file.filename);
+            if args.resume
+                && args.text_mode
+                && is_text(&file.data)
+                && extracted_size(&output_dir, &path, &filename, args.sanitize)
+                    == Some(crlf_to_lf(&file.data).len() as u64)
+            {
+                status!("[+] Skipping {}", file.full_path());
+                continue;
+            }
 
-                let mut output_file =
-                    File::create(file_path).expect("Couldn't create file in output directory");
-                output_file
-                    .write_all(&file.data)
-                    .expect("Couldn't write to file");
+            // The manifest and the digest are of the files as stored in the image
+            if let Some(manifest) = manifest.as_mut() {
+                export::write_manifest_entry(manifest, &file).expect("Couldn't write manifest");
+                manifest_entries += 1;
+            }
+            if let Some(merkle) = merkle.as_mut() {
+                merkle.add(&file);
             }
-            println!("[+] Extracted into {}", output_dir);
+
+            (file.path, file.filename) = (path, filename);
+            tree.write(&file, &mut write);
         }
     }
+    let report = tree.finish();
+
+    if let Some(mut order_log) = order_log {
+        order_log.flush().expect("Couldn't write order log");
+    }
+    if let (Some(mut manifest), Some(manifest_file)) = (manifest, args.manifest.as_deref()) {
+        manifest.flush().expect("Couldn't write manifest");
+        status!(
+            "[+] Wrote the manifest of {} files into {}",
+            manifest_entries,
+            manifest_file
+        );
+    }
+    status!("[+] Decompressed {} chunks", timings.len());
+    if args.timing {
+        print_timings(&timings);
+    }
+    print_report(&report, &final_dir);
+
+    // A partial tree, or one with warnings under --strict, is never moved into place
+    let failed = report.failed > 0 || strict_failed();
+    if args.staged && failed {
+        let _ = std::fs::remove_dir_all(&output_dir);
+    } else if args.staged {
+        if let Err(e) = std::fs::rename(&output_dir, &final_dir) {
+            status!(
+                "[-] Couldn't move {} into place as {}: {}",
+                output_dir,
+                final_dir,
+                e
+            );
+            let _ = std::fs::remove_dir_all(&output_dir);
+            fail();
+        }
+        written_files = written_files
+            .into_iter()
+            .map(|x| Path::new(&final_dir).join(x.strip_prefix(&output_dir).unwrap()))
+            .collect();
+    }
+    if failed {
+        check_strict();
+        fail();
+    }
+
+    if !encoded_names.is_empty() {
+        let names_file = format!("{}.names", final_dir);
+        export::write_encoded_names(&names_file, &encoded_names)
+            .expect("Couldn't write encoded names");
+        status!(
+            "[+] Wrote the original names of {} encoded files into {}",
+            encoded_names.len(),
+            names_file
+        );
+    }
+
+    if let Some(manifest) = args.fakeroot_manifest {
+        let relative_paths = written_files
+            .iter()
+            .map(|x| x.strip_prefix(&final_dir).unwrap().to_path_buf())
+            .collect::<Vec<PathBuf>>();
+        export::write_fakeroot_manifest(&manifest, &relative_paths)
+            .expect("Couldn't write fakeroot manifest");
+        status!("[+] Wrote the fakeroot manifest into {}", manifest);
+    }
+
+    if let Some(merkle) = merkle {
+        status!("[+] Merkle root: {}", merkle.root());
+    }
+
+    if args.print0 {
+        let mut stdout = std::io::stdout().lock();
+        for file_path in written_files {
+            stdout
+                .write_all(file_path.as_os_str().as_encoded_bytes())
+                .and_then(|_| stdout.write_all(b"\0"))
+                .expect("Couldn't write to stdout");
+        }
+    }
+
+    if let Some(index_map) = args.index_map {
+        export::write_index_map(&index_map, &minifs).expect("Couldn't write index map");
+        status!("[+] Wrote index map into {}", index_map);
+    }
+
+    if args.follow {
+        follow(
+            binary,
+            parse,
+            &output_stem,
+            args.max_path_depth,
+            minifs.get_header_start() + minifs.image_len(),
+        );
+    }
+}
+
+/// Runs `command` once per file with the file's data on its stdin, `threads` commands at a time,
+/// and reports the exit codes in file order
+fn pipe_each(command: &str, files: &[DecompressedFile], threads: usize) {
+    let next = Mutex::new(files.iter().enumerate());
+    let statuses = Mutex::new(files.iter().map(|_| None).collect::<Vec<_>>());
+    std::thread::scope(|scope| {
+        for _ in 0..threads.min(files.len()) {
+            scope.spawn(|| loop {
+                let Some((index, file)) = next.lock().unwrap().next() else {
+                    return;
+                };
+                let status = run_piped(command, file);
+                statuses.lock().unwrap()[index] = Some(status);
+            });
+        }
+    });
+
+    let mut failed = 0;
+    for (file, status) in files.iter().zip(statuses.into_inner().unwrap()) {
+        match status.unwrap() {
+            Ok(status) if status.success() => status!("[+] {}: {}", file.full_path(), status),
+            Ok(status) => {
+                failed += 1;
+                warning!("{}: {}", file.full_path(), status);
+            }
+            Err(e) => {
+                failed += 1;
+                warning!("{}: couldn't run the command: {}", file.full_path(), e);
+            }
+        }
+    }
+    status!(
+        "[+] Ran the command on {} files, {} failed",
+        files.len(),
+        failed
+    );
+}
+
+fn run_piped(command: &str, file: &DecompressedFile) -> std::io::Result<ExitStatus> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = process::Command::new(shell)
+        .args([flag, command])
+        .env("MINIFS_PATH", file.full_path())
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    // Commands such as `head` may exit without reading all their input
+    let written = child.stdin.take().unwrap().write_all(&file.data);
+    match written {
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+            let _ = child.kill();
+            child.wait()?;
+            Err(e)
+        }
+        _ => child.wait(),
+    }
+}
+
+/// Writes the file at `path` to stdout, reading its chunks from `binary` at the locations recorded
+/// in `index` without parsing the binary
+fn cat_from_index(binary: &str, index: &str, path: &str, ignore_case: bool) {
+    let index = std::fs::read_to_string(index).expect("Couldn't read index");
+    let mut chunks = Vec::new();
+    let mut file = None;
+    let path = path.trim_start_matches('/');
+    for line in index.lines() {
+        let fields = line.splitn(5, '\t').collect::<Vec<&str>>();
+        let number = |x: usize| fields.get(x).and_then(|x| x.parse::<usize>().ok());
+        match fields[0] {
+            "chunk" => chunks.push((number(1), number(2), number(3))),
+            "file" if fields.len() == 5 => {
+                let matches = if ignore_case {
+                    fields[4].to_lowercase() == path.to_lowercase()
+                } else {
+                    fields[4] == path
+                };
+                if matches && file.is_none() {
+                    file = Some((number(1), number(2), number(3)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Some((Some(chunk_number), Some(offset_in_chunk), Some(file_size))) = file else {
+        status!("[-] {} not found in index", path);
+        fail();
+    };
+
+    let content = std::fs::read(binary).expect("File not found");
+    let mut data = Vec::with_capacity(file_size);
+    let mut offset_in_chunk = offset_in_chunk;
+    for chunk in chunks.iter().skip(chunk_number) {
+        let (Some(start), Some(size), Some(decompressed_size)) = *chunk else {
+            status!("[-] Invalid chunk in index");
+            fail();
+        };
+        let Some(compressed) = content.get(start..start + size) else {
+            status!("[-] Chunk at {:#x} is outside of {}", start, binary);
+            fail();
+        };
+        let decompressed = minifs_extractor::decompress_chunk(compressed, decompressed_size)
+            .unwrap_or_else(|e| {
+                status!("[-] {}", e);
+                fail();
+            });
+
+        let end = decompressed
+            .len()
+            .min(offset_in_chunk + file_size - data.len());
+        data.extend_from_slice(decompressed.get(offset_in_chunk..end).unwrap_or_default());
+        offset_in_chunk = 0;
+        if data.len() >= file_size {
+            break;
+        }
+    }
+
+    std::io::stdout()
+        .write_all(&data)
+        .expect("Couldn't write to stdout");
+}
+
+fn print_magic_offsets(binary: &str) {
+    let (content, _) = read_binary(binary);
+    let candidates = minifs_extractor::scan_magics(&content);
+    for candidate in candidates.iter() {
+        let verdict = match (
+            candidate.header_plausible,
+            candidate.configuration_word_matches,
+        ) {
+            (false, _) => "implausible header",
+            (true, false) => "plausible header, LZMA configuration word doesn't match",
+            (true, true) => "valid",
+        };
+        status!("[+] {:#x}: {}", candidate.offset, verdict);
+    }
+    status!("[+] Found {} minifs magics", candidates.len());
+}
+
+/// Polls `binary` for minifs images appended after `offset` and extracts each one into its own
+/// `<output_stem>_<header offset>.extracted` directory once it is complete. Runs until killed.
+fn follow(
+    binary: &str,
+    parse: &ParseOptions,
+    output_stem: &str,
+    max_path_depth: usize,
+    mut offset: usize,
+) -> ! {
+    status!(
+        "[+] Following {} for new images after {:#x}",
+        binary,
+        offset
+    );
+    let mut fd = File::open(binary).expect("Couldn't open binary");
+    fd.seek(SeekFrom::Start(offset as u64))
+        .expect("Couldn't seek binary");
+    // Bytes after `offset` which don't hold a complete image yet
+    let mut pending = Vec::new();
+    loop {
+        fd.read_to_end(&mut pending).expect("Couldn't read binary");
+
+        // Nothing before the first magic can start an image, a magic may only be partly written
+        let skipped = match minifs_extractor::scan_magics(&pending).first() {
+            Some(magic) => magic.offset,
+            None => pending.len().saturating_sub(HEADER_MAGIC_LEN - 1),
+        };
+        pending.drain(..skipped);
+        offset += skipped;
+
+        // Images still being written are retried on the next poll
+        let parsed = MiniFs::parse_with_options(pending.clone(), &parse.to_minifs_options()).ok();
+        let Some(Parsed { minifs, .. }) =
+            parsed.filter(|x| x.minifs.content_len() >= x.minifs.image_len())
+        else {
+            std::thread::sleep(FOLLOW_POLL_INTERVAL);
+            continue;
+        };
+
+        let header_start = offset + minifs.get_header_start();
+        let output_dir = format!("{}_{:#x}.extracted", output_stem, header_start);
+        status!("[+] Found minifs header at {:#x}", header_start);
+
+        let report = minifs.run_extraction(&ExtractionOptions {
+            max_path_depth,
+            ..ExtractionOptions::new(&output_dir)
+        });
+        print_report(&report, &output_dir);
+
+        let image_end = minifs.get_header_start() + minifs.image_len();
+        pending.drain(..image_end);
+        offset += image_end;
+    }
+}
+
+fn list(binary: &str, parse: &ParseOptions, args: ListOptions) {
+    // Listing only reads the Table of Files, unless --strict needs the chunks to check the image
+    // size
+    let options = parse
+        .to_minifs_options()
+        .lazy_chunks(!STRICT.load(Ordering::Relaxed));
+    let (minifs, _) = open(binary, &options);
+
+    if args.jsonl {
+        export::write_jsonl(&mut BufWriter::new(std::io::stdout().lock()), &minifs)
+            .expect("Couldn't write to stdout");
+        return;
+    }
+
+    if let Some(csv) = args.csv {
+        export::write_csv(&csv, &minifs).expect("Couldn't write CSV file");
+        status!("[+] Wrote {} entries into {}", minifs.get_files_no(), csv);
+        return;
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(sqlite) = args.sqlite {
+        export::write_sqlite(&sqlite, &minifs).expect("Couldn't write SQLite database");
+        status!(
+            "[+] Wrote {} entries into {}",
+            minifs.get_files_no(),
+            sqlite
+        );
+        return;
+    }
+
+    if args.list_dirs {
+        let mut dirs: BTreeMap<String, usize> = BTreeMap::new();
+        for file in minifs.list() {
+            let dir = path_components(&file.path).collect::<Vec<&str>>().join("/");
+            *dirs.entry(format!("/{}", dir)).or_default() += 1;
+        }
+        for (dir, files_no) in dirs.iter() {
+            status!("[+] {} ({} files)", dir, files_no);
+        }
+        status!("[+] Found {} directories in minifs", dirs.len());
+        return;
+    }
+
+    for (entry, path) in minifs.entries() {
+        status!("[+] {} ({} bytes)", path, entry.file_size);
+    }
+    status!("[+] Found {} files in minifs", minifs.get_files_no());
+}
+
+fn info(binary: &str, parse: &ParseOptions, args: InfoOptions) {
+    let (minifs, mut fd) = open(binary, &parse.to_minifs_options());
+    let name = binary_name(binary);
+
+    let header_start = minifs.get_header_start();
+    if args.save_prefix && header_start > 0 {
+        let prefix_file = format!("_{}_prefix.bin", name);
+        let mut prefix = vec![0_u8; header_start];
+        fd.seek(SeekFrom::Start(0))
+            .and_then(|_| fd.read_exact(&mut prefix))
+            .expect("Couldn't read bytes before the minifs header");
+        File::create(&prefix_file)
+            .and_then(|mut x| x.write_all(&prefix))
+            .expect("Couldn't write prefix file");
+        status!("[+] Saved prefix into {}", prefix_file);
+    }
+
+    status!("[+] Table of Names: {} bytes", minifs.ton_bytes().len());
+    status!("[+] Table of Files: {} entries", minifs.get_files_no());
+    status!("[+] Table of Chunks: {} entries", minifs.get_chunks_no());
+    status!(
+        "[+] Total size is {} bytes ({} bytes compressed)",
+        minifs.total_size(),
+        minifs.total_compressed_size()
+    );
+    status!(
+        "[+] Image spans {:#x} to {:#x}",
+        header_start,
+        header_start + minifs.image_len()
+    );
+
+    if args.check_overlaps {
+        let files = minifs.list();
+        let overlaps = minifs.overlapping_files();
+        for (first, second) in overlaps.iter() {
+            warning!(
+                "{} overlaps {} in chunk {}",
+                files[*second].full_path(),
+                files[*first].full_path(),
+                files[*first].entry.chunk_number
+            );
+        }
+        status!("[+] Found {} overlapping files", overlaps.len());
+    }
+
+    if args.check_toc {
+        let chunks = minifs.chunks().map(|(x, _)| x).collect::<Vec<_>>();
+        let mut violations = 0;
+        for (i, pair) in chunks.windows(2).enumerate() {
+            let end = pair[0].chunk_offset as u64 + pair[0].chunk_size as u64;
+            let next = pair[1].chunk_offset as u64;
+            if next < pair[0].chunk_offset as u64 {
+                warning!("Chunk {} starts before chunk {}", i + 1, i);
+            } else if next > end {
+                warning!(
+                    "Gap of {} bytes between chunks {} and {}",
+                    next - end,
+                    i,
+                    i + 1
+                );
+            } else if next < end {
+                warning!("Chunks {} and {} overlap by {} bytes", i, i + 1, end - next);
+            } else {
+                continue;
+            }
+            violations += 1;
+        }
+        status!("[+] Found {} Table of Chunks violations", violations);
+    }
+
+    if args.dump_tables {
+        let tables_dir = format!("_{}.tables", name);
+        std::fs::create_dir_all(&tables_dir).expect("Couldn't create tables directory");
+        for (name, data) in [
+            ("ton.bin", minifs.ton_bytes()),
+            ("tof.bin", minifs.tof_bytes()),
+            ("toc.bin", minifs.toc_bytes()),
+        ] {
+            File::create(Path::new(&tables_dir).join(name))
+                .and_then(|mut x| x.write_all(data))
+                .expect("Couldn't write table file");
+        }
+        status!("[+] Dumped tables into {}", tables_dir);
+    }
+}
+
+fn verify(binary: &str, parse: &ParseOptions) {
+    let (minifs, _) = open(binary, &parse.to_minifs_options());
+    require_codec(&minifs);
+
+    let files = extract_files(&minifs, |_| true);
+    status!(
+        "[+] Decompressed {} files, all chunks match their declared size",
+        files.len()
+    );
+}
+
+#[cfg(feature = "mount")]
+fn mount_binary(binary: &str, mountpoint: &Path, parse: &ParseOptions) {
+    let (minifs, _) = open(binary, &parse.to_minifs_options());
+    require_codec(&minifs);
+
+    status!(
+        "[+] Mounting {} files on {}, unmount it to exit",
+        minifs.get_files_no(),
+        mountpoint.to_string_lossy()
+    );
+    if let Err(e) = mount::mount(minifs, mountpoint) {
+        status!("[-] Couldn't mount {}: {}", mountpoint.to_string_lossy(), e);
+        fail();
+    }
+}
+
+/// Extracts the files matching `predicate`, exiting on the first error
+fn extract_files<F>(minifs: &MiniFs, predicate: F) -> Vec<DecompressedFile>
+where
+    F: Fn(&FileInfo) -> bool,
+{
+    let extracted = minifs
+        .extract_where_reported(predicate)
+        .unwrap_or_else(|e| {
+            status!("[-] {}", e);
+            fail();
+        });
+    print_warnings(&extracted.warnings);
+    extracted.files
+}
+
+/// Extracts a single file, exiting if its chunk is too short to hold it
+fn extract_file(minifs: &MiniFs, file: &FileInfo) -> DecompressedFile {
+    let files = extract_files(minifs, |x| x.index == file.index);
+    files.into_iter().next().unwrap_or_else(|| {
+        status!(
+            "[-] {} couldn't be extracted, its chunk is too short",
+            file.full_path()
+        );
+        fail();
+    })
+}
+
+/// Prints the anomalies found while decompressing, which fail with --strict
+fn print_warnings(warnings: &[ParseWarning]) {
+    for warning in warnings {
+        warning!("{}", warning);
+    }
+}
+
+/// Exits if the chunks can't be decompressed, which only happens with --no-codec-check
+fn require_codec(minifs: &MiniFs) {
+    if !minifs.is_validated() {
+        status!("[-] The chunks don't use the LZMA codec, they can't be decompressed");
+        fail();
+    }
+}
+
+fn print_version_info() {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    println!(
+        "[+] minifs layout: {} byte header, {} byte TOF entries, {} byte TOC entries",
+        minifs_extractor::HEADER_SIZE,
+        minifs_extractor::TOF_ENTRY_SIZE,
+        minifs_extractor::TOC_ENTRY_SIZE
+    );
+    println!(
+        "[+] Codecs: lzma (configuration word {:#010X})",
+        minifs_extractor::LZMA_CONFIGURATION_WORD
+    );
+    let features = [
+        ("mount", cfg!(feature = "mount")),
+        ("http", cfg!(feature = "http")),
+        ("sqlite", cfg!(feature = "sqlite")),
+        ("tokio", cfg!(feature = "tokio")),
+    ];
+    for (feature, enabled) in features {
+        let state = if enabled { "enabled" } else { "disabled" };
+        println!("[+] Feature {}: {}", feature, state);
+    }
+}
+
+/// Prints the outcome of an extraction, with the files which weren't written and why
+fn print_report(report: &ExtractionReport, output_dir: &str) {
+    print_warnings(&report.warnings);
+    for (path, problem) in report.problems.iter() {
+        if problem.is_skipped() {
+            warning!("Skipping {}, {}", path, problem);
+        } else {
+            status!("[-] Couldn't extract {}: {}", path, problem);
+        }
+    }
+    status!(
+        "[+] Extracted {} files and {} directories ({} bytes) into {} in {:.3}s",
+        report.written,
+        report.directories,
+        report.total_bytes,
+        output_dir,
+        report.elapsed.as_secs_f64()
+    );
+    if report.failed > 0 {
+        status!("[-] Failed to extract {} files", report.failed);
+    }
+}
+
+fn print_timings(timings: &[ChunkTiming]) {
+    let mut total = Duration::ZERO;
+    status!("[+] Chunk    Time (ms)  Cumulative (ms)");
+    for timing in timings {
+        total += timing.elapsed;
+        status!(
+            "[+] {:<8} {:>9.3}  {:>15.3}",
+            timing.chunk_number,
+            timing.elapsed.as_secs_f64() * 1000.0,
+            total.as_secs_f64() * 1000.0
+        );
+    }
+}
+
+/// Exits if `path` isn't an existing directory
+fn require_dir(path: &Path) {
+    if !path.is_dir() {
+        status!("[-] Directory {} doesn't exist", path.to_string_lossy());
+        fail();
+    }
+}
+
+/// Reports the files of `files` which are missing or differ in `compare_dir`, exiting with an
+/// error if there is any
+fn compare_to_dir(compare_dir: &str, files: &[DecompressedFile], rename_map: &[(String, String)]) {
+    let mut mismatches = 0;
+    for file in files {
+        let Some((_, file_path)) = output_paths(
+            Path::new(compare_dir),
+            &rename_path(rename_map, &file.path),
+            &file.filename,
+        ) else {
+            status!("[-] {} could escape {}", file.full_path(), compare_dir);
+            mismatches += 1;
+            continue;
+        };
+        if file.filename.is_empty() {
+            if !file_path.is_dir() {
+                status!("[-] Missing {}/", file_path.to_string_lossy());
+                mismatches += 1;
+            }
+            continue;
+        }
+
+        match std::fs::read(&file_path) {
+            Err(_) => {
+                status!("[-] Missing {}", file_path.to_string_lossy());
+                mismatches += 1;
+            }
+            Ok(data) if data != file.data => {
+                status!("[-] Mismatch {}", file_path.to_string_lossy());
+                mismatches += 1;
+            }
+            Ok(_) => {}
+        }
+    }
+
+    if mismatches > 0 {
+        status!(
+            "[-] {} of {} files differ from {}",
+            mismatches,
+            files.len(),
+            compare_dir
+        );
+        fail();
+    }
+    status!("[+] All {} files match {}", files.len(), compare_dir);
+}
+
+/// Reads the `from<TAB>to` lines of a rename map, sorted longest prefix first
+fn read_rename_map(file_name: &str) -> Vec<(String, String)> {
+    let content = std::fs::read_to_string(file_name).expect("Couldn't read rename map");
+    parse_rename_map(&content)
+}
+
+/// Parses the `from<TAB>to` lines of a rename map, longest prefix first
+fn parse_rename_map(content: &str) -> Vec<(String, String)> {
+    let mut rename_map = content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let Some((from, to)) = line.split_once('\t') else {
+                status!("[-] Invalid rename map line: {}", line);
+                fail();
+            };
+            (from.to_string(), to.to_string())
+        })
+        .collect::<Vec<(String, String)>>();
+    rename_map.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+    rename_map
+}
+
+/// Sizes and SHA-256 digests of the files of a manifest written by --manifest, by `path/filename`.
+/// Manifests from `list --jsonl` only have the sizes.
+fn read_manifest(file_name: &str) -> HashMap<String, (u64, Option<String>)> {
+    let content = std::fs::read_to_string(file_name).expect("Couldn't read manifest");
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let entry = serde_json::from_str::<export::JsonEntry>(line).unwrap_or_else(|_| {
+                status!("[-] Invalid manifest line: {}", line);
+                fail();
+            });
+            let full_path = path_components(&entry.path)
+                .chain(std::iter::once(entry.filename.as_str()))
+                .collect::<Vec<&str>>()
+                .join("/");
+            (full_path, (entry.size, entry.sha256))
+        })
+        .collect()
+}
+
+/// Rewrites the first matching prefix of `path`. Prefixes are matched against the path with a
+/// trailing '/', so `etc/` matches the `etc` directory and everything below it.
+fn rename_path(rename_map: &[(String, String)], path: &str) -> String {
+    let path = format!("{}/", path.trim_end_matches('/'));
+    for (from, to) in rename_map {
+        if let Some(rest) = path.strip_prefix(from.as_str()) {
+            return format!("{}{}", to, rest).trim_end_matches('/').to_string();
+        }
+    }
+
+    path.trim_end_matches('/').to_string()
+}
+
+/// Size of a file extracted by a previous run, under the name it was written as. With --sanitize
+/// it may have been written under its sanitized filename instead.
+fn extracted_size(output_dir: &str, path: &str, filename: &str, sanitize: bool) -> Option<u64> {
+    let sanitized = sanitize_filename(filename);
+    let filenames = [
+        Some(filename),
+        (sanitize && sanitized != filename).then_some(&*sanitized),
+    ];
+    let size = filenames.into_iter().flatten().find_map(|filename| {
+        // Files which could escape the output directory are left to the extraction to reject
+        let (_, file_path) = output_paths(Path::new(output_dir), path, filename)?;
+        std::fs::metadata(&file_path)
+            .ok()
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+    });
+    size
+}
+
+/// Conservatively guesses if `data` is text: no NUL byte and almost only printable ASCII or
+/// whitespace
+fn is_text(data: &[u8]) -> bool {
+    if data.is_empty() || data.contains(&0) {
+        return false;
+    }
+
+    let printable = data
+        .iter()
+        .filter(|x| x.is_ascii_graphic() || matches!(x, b' ' | b'\t' | b'\n' | b'\r'))
+        .count();
+    printable * 100 >= data.len() * TEXT_PRINTABLE_PERCENT
+}
+
+fn crlf_to_lf(data: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(data.len());
+    for (index, byte) in data.iter().enumerate() {
+        if *byte != b'\r' || data.get(index + 1) != Some(&b'\n') {
+            normalized.push(*byte);
+        }
+    }
+    normalized
+}
+
+/// Percent-encodes the control characters of a filename, along with '%' so the encoding can be
+/// reversed
+fn encode_filename(filename: &str) -> String {
+    let mut encoded = String::new();
+    for x in filename.chars() {
+        if x.is_control() || x == '%' {
+            let mut buffer = [0_u8; 4];
+            for byte in x.encode_utf8(&mut buffer).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(x);
+        }
+    }
+    encoded
+}
+
+/// Replaces the characters which are illegal in file names on Windows or are control characters
+fn sanitize_filename(filename: &str) -> String {
+    filename
+        .chars()
+        .map(|x| match x {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            x if x.is_control() => '_',
+            x => x,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_the_longest_prefix() {
+        let rename_map = parse_rename_map("etc/\tconfig/\netc/init.d/\tinit/\n");
+        assert_eq!(rename_path(&rename_map, "etc"), "config");
+        assert_eq!(rename_path(&rename_map, "etc/ssh"), "config/ssh");
+        assert_eq!(rename_path(&rename_map, "etc/init.d"), "init");
+        // Prefixes only match whole components
+        assert_eq!(rename_path(&rename_map, "etcetera"), "etcetera");
+        assert_eq!(rename_path(&rename_map, "usr/etc"), "usr/etc");
+    }
+
+    #[test]
+    fn renamed_paths_are_still_checked() {
+        let rename_map = parse_rename_map("etc/\t../\n");
+        let renamed = rename_path(&rename_map, "etc/ssh");
+        assert_eq!(renamed, "../ssh");
+        assert_eq!(
+            output_paths(Path::new("out"), &renamed, "sshd_config"),
+            None
+        );
+    }
 }
@@ -1,25 +1,80 @@
-use crate::ParseError;
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    io::Read,
+    ops::{ControlFlow, Range},
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use lzma::{Direction, LzmaError, LzmaReader};
+
+use crate::{
+    output::{
+        write_atomic, ExtractionOptions, ExtractionProblem, ExtractionReport, OutputTree,
+        WRITE_PIECE_SIZE,
+    },
+    ParseError, ParseWarning,
+};
 
 const HEADER_MAGIC_NUMBER: &[u8] = b"MINIFS";
-const HEADER_SIZE: usize = 32;
+/// Size of the minifs header, the Table of Names starts right after it
+pub const HEADER_SIZE: usize = 32;
+
+/// Size of a single Table of Files entry
+pub const TOF_ENTRY_SIZE: usize = 20;
+/// Size of a single Table of Chunks entry
+pub const TOC_ENTRY_SIZE: usize = 12;
 
-const TOF_ENTRY_SIZE: usize = 20;
-const TOC_ENTRY_SIZE: usize = 12;
+/// The first word of every LZMA chunk described by the documentation
+pub const LZMA_CONFIGURATION_WORD: u32 = 0x5D000080;
+
+/// Files buffered by [`MiniFs::extract_async`] before decompression waits for the receiver
+#[cfg(feature = "tokio")]
+const ASYNC_CHANNEL_CAPACITY: usize = 16;
+
+/// Byte order of the header and table fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
 
-const LZMA_CONFIGURATION_WORD: u32 = 0x5D000080;
+impl Endianness {
+    fn read_u32(self, data: &[u8]) -> u32 {
+        let bytes = data[0..4].try_into().unwrap();
+        match self {
+            Endianness::Big => u32::from_be_bytes(bytes),
+            Endianness::Little => u32::from_le_bytes(bytes),
+        }
+    }
+}
+
+impl std::fmt::Display for Endianness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endianness::Big => write!(f, "big-endian"),
+            Endianness::Little => write!(f, "little-endian"),
+        }
+    }
+}
 
+#[derive(Clone)]
 struct MiniFsOffsets {
     /// Table of Names
     pub ton_offset: usize,
+    pub ton_size: usize,
     /// Table of Files
     pub tof_offset: usize,
     /// Table of chunks
     pub toc_offset: usize,
     /// Raw chunks
     pub raw_chunks_offset: usize,
+    /// What the chunk offsets are relative to, the raw chunks unless they are absolute
+    pub chunks_base: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ToFEntry {
     pub ton_path_offset: u32,
     pub ton_file_name_offset: u32,
@@ -30,17 +85,21 @@ pub struct ToFEntry {
 
 impl ToFEntry {
     pub fn parse(data: [u8; TOF_ENTRY_SIZE]) -> Self {
+        Self::parse_with_endianness(data, Endianness::Big)
+    }
+
+    pub fn parse_with_endianness(data: [u8; TOF_ENTRY_SIZE], endianness: Endianness) -> Self {
         Self {
-            ton_path_offset: u32::from_be_bytes(data[0..4].try_into().unwrap()),
-            ton_file_name_offset: u32::from_be_bytes(data[4..8].try_into().unwrap()),
-            chunk_number: u32::from_be_bytes(data[8..12].try_into().unwrap()),
-            offset_in_chunk: u32::from_be_bytes(data[12..16].try_into().unwrap()),
-            file_size: u32::from_be_bytes(data[16..20].try_into().unwrap()),
+            ton_path_offset: endianness.read_u32(&data[0..4]),
+            ton_file_name_offset: endianness.read_u32(&data[4..8]),
+            chunk_number: endianness.read_u32(&data[8..12]),
+            offset_in_chunk: endianness.read_u32(&data[12..16]),
+            file_size: endianness.read_u32(&data[16..20]),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ToCEntry {
     pub chunk_offset: u32,
     pub chunk_size: u32,
@@ -49,81 +108,575 @@ pub struct ToCEntry {
 
 impl ToCEntry {
     pub fn parse(data: [u8; TOC_ENTRY_SIZE]) -> Self {
+        Self::parse_with_endianness(data, Endianness::Big)
+    }
+
+    pub fn parse_with_endianness(data: [u8; TOC_ENTRY_SIZE], endianness: Endianness) -> Self {
         Self {
-            chunk_offset: u32::from_be_bytes(data[0..4].try_into().unwrap()),
-            chunk_size: u32::from_be_bytes(data[4..8].try_into().unwrap()),
-            decompressed_size: u32::from_be_bytes(data[8..12].try_into().unwrap()),
+            chunk_offset: endianness.read_u32(&data[0..4]),
+            chunk_size: endianness.read_u32(&data[4..8]),
+            decompressed_size: endianness.read_u32(&data[8..12]),
         }
     }
 }
 
+/// File metadata resolved from the tables, without any decompression
+#[derive(Clone)]
+pub struct FileInfo<'a> {
+    /// Position of the entry in the Table of Files
+    pub index: usize,
+    pub path: String,
+    pub filename: String,
+    pub entry: &'a ToFEntry,
+}
+
+impl FileInfo<'_> {
+    pub fn full_path(&self) -> String {
+        join_path(&self.path, &self.filename)
+    }
+}
+
+#[derive(Clone)]
 pub struct DecompressedFile {
+    /// Index of the entry in the Table of Files
+    pub index: usize,
     pub path: String,
     pub filename: String,
+    /// Chunks the data was read from, usually just the entry's `chunk_number`
+    pub chunks: Range<u32>,
     pub data: Vec<u8>,
 }
 
+impl DecompressedFile {
+    pub fn full_path(&self) -> String {
+        join_path(&self.path, &self.filename)
+    }
+}
+
+/// Time spent decompressing one chunk, see `MiniFs::extract_where_timed`
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkTiming {
+    pub chunk_number: u32,
+    pub elapsed: Duration,
+}
+
+/// Files returned by [`MiniFs::extract_where_reported`], along with what happened while
+/// decompressing them
+pub struct Extracted {
+    pub files: Vec<DecompressedFile>,
+    /// How long each decompressed chunk took, in the order they were decompressed
+    pub timings: Vec<ChunkTiming>,
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// A `MINIFS` magic found by `scan_magics`
+#[derive(Debug, Clone)]
+pub struct MagicCandidate {
+    pub offset: usize,
+    /// The header fields and table offsets fit in the image
+    pub header_plausible: bool,
+    /// The first chunk starts with the LZMA configuration word
+    pub configuration_word_matches: bool,
+}
+
+/// Header fields and table offsets, before any table is parsed
+struct Layout {
+    endianness: Endianness,
+    files_no: u32,
+    chunks_no: u32,
+    offsets: MiniFsOffsets,
+}
+
+/// Result of a successful parse: the filesystem and the recoverable anomalies found in the image
+pub struct Parsed {
+    pub minifs: MiniFs,
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Overrides for the layout assumptions made while parsing, to experiment with variant images
+#[derive(Debug, Clone)]
+pub struct MiniFsOptions {
+    /// Size of the header, the Table of Names starts right after it
+    pub header_size: usize,
+    /// Offset of a header field holding the Table of Names offset, for variants storing it
+    /// elsewhere in the image. The Table of Files then starts right after the header.
+    pub ton_offset_field: Option<usize>,
+    /// Accept chunks which don't start with the LZMA configuration word, see
+    /// [`MiniFs::parse_unchecked`]
+    pub no_codec_check: bool,
+    /// The chunk offsets are relative to the header instead of the raw chunks
+    pub absolute_chunk_offsets: bool,
+    /// How many bytes a name may span before its NUL terminator
+    pub name_scan_limit: usize,
+    /// Offset of the number of files in the header
+    pub files_no_offset: usize,
+    /// Offset of the size of the Table of Names in the header
+    pub ton_size_offset: usize,
+    /// Keep the bytes of chunks decompressing to less than their declared size, and slice the
+    /// files from what is available instead of failing
+    pub tolerate_size_mismatch: bool,
+    /// Defer parsing the Table of Chunks until a chunk is first needed, for callers which only
+    /// read the file list. The chunks are then not checked for overlaps, and the size of the
+    /// image isn't compared against the input.
+    pub lazy_chunks: bool,
+    /// Skip the checks made for every entry, for known good images in release builds: name
+    /// offsets within the Table of Names, NUL-terminated names, chunks not overlapping and files
+    /// starting within their chunk. Debug builds always make these checks.
+    pub trust_input: bool,
+    /// Entries whose path and filename offsets are the same point to a single full path string,
+    /// which is split into the path and the filename at its last `/`
+    pub combined_names: bool,
+    /// Only look for the header magic within the first bytes of the input, to avoid false
+    /// positives within compressed data. The whole input is searched by default.
+    pub header_search_window: Option<usize>,
+    /// Count the chunks from the Table of Chunks itself instead of the chunk of the last file,
+    /// for images whose files aren't ordered by chunk. The table is taken to end where the first
+    /// LZMA configuration word follows it.
+    pub infer_chunks: bool,
+}
+
+impl Default for MiniFsOptions {
+    fn default() -> Self {
+        Self {
+            header_size: HEADER_SIZE,
+            ton_offset_field: None,
+            no_codec_check: false,
+            absolute_chunk_offsets: false,
+            name_scan_limit: 4096,
+            files_no_offset: 0x14,
+            ton_size_offset: 0x1c,
+            tolerate_size_mismatch: false,
+            lazy_chunks: false,
+            trust_input: false,
+            combined_names: false,
+            header_search_window: None,
+            infer_chunks: false,
+        }
+    }
+}
+
+/// Builder methods, to only override some of the defaults, as in
+/// `MiniFsOptions::new().header_size(0x40).absolute_chunk_offsets(true)`
+impl MiniFsOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets [`MiniFsOptions::header_size`]
+    pub fn header_size(mut self, header_size: usize) -> Self {
+        self.header_size = header_size;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::ton_offset_field`]
+    pub fn ton_offset_field(mut self, ton_offset_field: usize) -> Self {
+        self.ton_offset_field = Some(ton_offset_field);
+        self
+    }
+
+    /// Sets [`MiniFsOptions::no_codec_check`]
+    pub fn no_codec_check(mut self, no_codec_check: bool) -> Self {
+        self.no_codec_check = no_codec_check;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::absolute_chunk_offsets`]
+    pub fn absolute_chunk_offsets(mut self, absolute_chunk_offsets: bool) -> Self {
+        self.absolute_chunk_offsets = absolute_chunk_offsets;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::name_scan_limit`]
+    pub fn name_scan_limit(mut self, name_scan_limit: usize) -> Self {
+        self.name_scan_limit = name_scan_limit;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::files_no_offset`]
+    pub fn files_no_offset(mut self, files_no_offset: usize) -> Self {
+        self.files_no_offset = files_no_offset;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::ton_size_offset`]
+    pub fn ton_size_offset(mut self, ton_size_offset: usize) -> Self {
+        self.ton_size_offset = ton_size_offset;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::tolerate_size_mismatch`]
+    pub fn tolerate_size_mismatch(mut self, tolerate_size_mismatch: bool) -> Self {
+        self.tolerate_size_mismatch = tolerate_size_mismatch;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::lazy_chunks`]
+    pub fn lazy_chunks(mut self, lazy_chunks: bool) -> Self {
+        self.lazy_chunks = lazy_chunks;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::trust_input`]
+    pub fn trust_input(mut self, trust_input: bool) -> Self {
+        self.trust_input = trust_input;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::combined_names`]
+    pub fn combined_names(mut self, combined_names: bool) -> Self {
+        self.combined_names = combined_names;
+        self
+    }
+
+    /// Sets [`MiniFsOptions::header_search_window`]
+    pub fn header_search_window(mut self, header_search_window: usize) -> Self {
+        self.header_search_window = Some(header_search_window);
+        self
+    }
+
+    /// Sets [`MiniFsOptions::infer_chunks`]
+    pub fn infer_chunks(mut self, infer_chunks: bool) -> Self {
+        self.infer_chunks = infer_chunks;
+        self
+    }
+
+    /// Whether the checks skipped by [`MiniFsOptions::trust_input`] are made
+    fn check_entries(&self) -> bool {
+        !self.trust_input || cfg!(debug_assertions)
+    }
+}
+
+/// The tables of a minifs, parsed relative to its header
+struct Tables {
+    validated: bool,
+    endianness: Endianness,
+    offsets: MiniFsOffsets,
+    files: Vec<ToFEntry>,
+    chunks_no: u32,
+    /// Not parsed yet with `MiniFsOptions::lazy_chunks`
+    chunks: Option<Vec<ToCEntry>>,
+}
+
 // https://arxiv.org/html/2407.05064v1
+#[derive(Clone)]
 pub struct MiniFs {
-    content: Vec<u8>,
+    /// Shared so clones of a parsed filesystem don't copy the image
+    content: Arc<[u8]>,
     header_start: usize,
+    validated: bool,
+    endianness: Endianness,
     offsets: MiniFsOffsets,
     files: Vec<ToFEntry>,
-    chunks: Vec<ToCEntry>,
+    chunks_no: u32,
+    /// Parsed on first use, see [`MiniFs::chunk_entries`]
+    chunks: OnceLock<Vec<ToCEntry>>,
+    tolerate_size_mismatch: bool,
+    /// See [`MiniFsOptions::trust_input`]
+    check_entries: bool,
+    combined_names: bool,
+    /// See [`MiniFsOptions::name_scan_limit`]
+    name_scan_limit: usize,
 }
 
 impl MiniFs {
-    pub fn parse(content: Vec<u8>) -> Result<Self, ParseError> {
-        let header_start =
-            find_bytes(&content, HEADER_MAGIC_NUMBER).ok_or(ParseError::InvalidHeader)?;
+    /// Parses the first valid minifs found in `content`. If a `MINIFS` magic turns out to be a false
+    /// positive, the search continues with the next occurrence. Recoverable anomalies found along
+    /// the way are returned next to the filesystem.
+    pub fn parse(content: Vec<u8>) -> Result<Parsed, ParseError> {
+        Self::parse_internal(content, true, &MiniFsOptions::default())
+    }
+
+    /// Same as [`MiniFs::parse`], using `options` instead of the documented layout
+    pub fn parse_with_options(
+        content: Vec<u8>,
+        options: &MiniFsOptions,
+    ) -> Result<Parsed, ParseError> {
+        Self::parse_internal(content, !options.no_codec_check, options)
+    }
+
+    /// Same as [`MiniFs::parse`], but skips the LZMA configuration word validation. The returned
+    /// filesystem is unvalidated, so its chunks may not be decompressible.
+    pub fn parse_unchecked(content: Vec<u8>) -> Result<Parsed, ParseError> {
+        Self::parse_internal(content, false, &MiniFsOptions::default())
+    }
 
+    fn parse_internal(
+        mut content: Vec<u8>,
+        validate: bool,
+        options: &MiniFsOptions,
+    ) -> Result<Parsed, ParseError> {
+        if content.is_empty() {
+            return Err(ParseError::EmptyInput);
+        }
+
+        let mut first_error = None;
+        let mut search_start = 0;
+        let mut warnings = Vec::new();
+
+        // A magic starting within the window may end past it
+        let search_end = options.header_search_window.map_or(content.len(), |x| {
+            x.saturating_add(HEADER_MAGIC_NUMBER.len() - 1)
+                .min(content.len())
+        });
+        while let Some(position) = content
+            .get(search_start..search_end)
+            .and_then(|x| find_bytes(x, HEADER_MAGIC_NUMBER))
+        {
+            let header_start = search_start + position;
+            match Self::parse_tables(&content[header_start..], validate, options) {
+                Ok(tables) => {
+                    content.drain(..header_start);
+                    let minifs = Self {
+                        header_start,
+                        content: content.into(),
+                        validated: tables.validated,
+                        endianness: tables.endianness,
+                        offsets: tables.offsets,
+                        files: tables.files,
+                        chunks_no: tables.chunks_no,
+                        chunks: tables.chunks.map(OnceLock::from).unwrap_or_default(),
+                        tolerate_size_mismatch: options.tolerate_size_mismatch,
+                        check_entries: options.check_entries(),
+                        combined_names: options.combined_names,
+                        name_scan_limit: options.name_scan_limit,
+                    };
+                    warnings.extend(minifs.layout_warnings());
+                    return Ok(Parsed { minifs, warnings });
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                    warnings.push(ParseWarning::SkippedMagic(header_start));
+                    search_start = header_start + 1;
+                }
+            }
+        }
+
+        Err(first_error.unwrap_or(ParseError::InvalidHeader))
+    }
+
+    /// Anomalies of a parsed filesystem that don't prevent extracting it
+    fn layout_warnings(&self) -> Vec<ParseWarning> {
+        let mut warnings = Vec::new();
+        if self.header_start > 0 {
+            warnings.push(ParseWarning::SkippedPadding(self.header_start));
+        }
+
+        if !self.validated {
+            warnings.push(ParseWarning::Unvalidated);
+        }
+
+        if !self.combined_names {
+            let shared = self
+                .files
+                .iter()
+                .filter(|x| {
+                    x.ton_path_offset == x.ton_file_name_offset
+                        && self
+                            .resolve_name(x.ton_path_offset)
+                            .is_ok_and(|x| !x.is_empty())
+                })
+                .count();
+            if shared > 0 {
+                warnings.push(ParseWarning::SharedNameOffsets(shared));
+            }
+        }
+
+        // Comparing sizes needs the chunks, which were deferred
+        if self.chunks.get().is_none() {
+            return warnings;
+        }
+
+        let expected_size = self.expected_size();
+        let available_size = self.content_len() as u64;
+        if available_size < expected_size {
+            warnings.push(ParseWarning::Truncated {
+                available: available_size,
+                expected: expected_size,
+            });
+        } else if available_size > expected_size {
+            warnings.push(ParseWarning::TrailingData(available_size - expected_size));
+        }
+
+        warnings
+    }
+
+    /// Parses and validates the tables of a minifs starting at the beginning of `content`
+    fn parse_tables(
+        content: &[u8],
+        validate: bool,
+        options: &MiniFsOptions,
+    ) -> Result<Tables, ParseError> {
+        let Layout {
+            endianness,
+            files_no,
+            chunks_no,
+            offsets,
+        } = Self::parse_layout(content, options)?;
+
+        let files = Self::parse_files_internal(content, &offsets, files_no, endianness);
+        // Files aren't necessarily ordered by chunk, any of them may point past the table
+        if let Some(file) = files.iter().find(|x| x.chunk_number >= chunks_no) {
+            return Err(ParseError::ChunkNumberOutOfRange {
+                chunk_number: file.chunk_number,
+                chunks_no,
+            });
+        }
+        let check_entries = options.check_entries();
+        let ton_size = offsets.ton_size as u32;
+        if check_entries
+            && files
+                .iter()
+                .any(|x| x.ton_path_offset >= ton_size || x.ton_file_name_offset >= ton_size)
+        {
+            return Err(ParseError::InvalidNameOffset);
+        }
+        if check_entries {
+            for name_offset in files
+                .iter()
+                .flat_map(|x| [x.ton_path_offset, x.ton_file_name_offset])
+            {
+                let offset = offsets.ton_offset + name_offset as usize;
+                read_string(content, offset, options.name_scan_limit)?;
+            }
+        }
+
+        let chunks = if options.lazy_chunks {
+            None
+        } else {
+            let chunks = Self::parse_chunks_internal(content, &offsets, chunks_no, endianness);
+            if check_entries && has_overlaps(&chunks) {
+                return Err(ParseError::OverlappingChunks);
+            }
+            Some(chunks)
+        };
+
+        let validated = has_configuration_word(content, &offsets);
+        if validate && !validated {
+            return Err(ParseError::UnsupportedVersion);
+        }
+
+        Ok(Tables {
+            validated,
+            endianness,
+            offsets,
+            files,
+            chunks_no,
+            chunks,
+        })
+    }
+
+    /// Reads the header of a minifs starting at the beginning of `content` and computes where its
+    /// tables are. The fields are read as big-endian, unless that makes the header implausible, in
+    /// which case little-endian is used.
+    fn parse_layout(content: &[u8], options: &MiniFsOptions) -> Result<Layout, ParseError> {
+        // The fields may be anywhere within a larger header
         let header = content
-            .iter()
-            .skip(header_start)
-            .take(HEADER_SIZE)
-            .copied()
-            .collect::<Vec<u8>>();
+            .get(..options.header_size)
+            .ok_or(ParseError::InvalidHeader)?;
 
-        let files_no = u32::from_be_bytes(get_offset(&header, 0x14, 4).try_into().unwrap());
-        let ton_size = u32::from_be_bytes(get_offset(&header, 0x1c, 4).try_into().unwrap());
+        let read_field = |endianness: Endianness, offset: usize| {
+            header
+                .get(offset..offset + 4)
+                .map(|x| endianness.read_u32(x))
+                .ok_or(ParseError::InvalidHeader)
+        };
+        let is_plausible = |endianness: Endianness| {
+            [options.files_no_offset, options.ton_size_offset]
+                .into_iter()
+                .all(|offset| {
+                    read_field(endianness, offset).is_ok_and(|x| x as usize <= content.len())
+                })
+        };
+        let endianness = [Endianness::Big, Endianness::Little]
+            .into_iter()
+            .find(|x| is_plausible(*x))
+            .ok_or(ParseError::InvalidHeader)?;
 
-        let content: Vec<u8> = content.into_iter().skip(header_start).collect();
+        let files_no = read_field(endianness, options.files_no_offset)?;
+        let ton_size = read_field(endianness, options.ton_size_offset)?;
 
-        let ton_offset = HEADER_SIZE;
-        let tof_offset = ton_offset + ton_size as usize;
-        let toc_offset = tof_offset + (TOF_ENTRY_SIZE * files_no as usize);
+        let (ton_offset, tof_offset) = match options.ton_offset_field {
+            Some(field) => {
+                let ton_offset = read_field(endianness, field)? as usize;
+                if ton_offset > content.len() {
+                    return Err(ParseError::InvalidHeader);
+                }
+                (ton_offset, options.header_size)
+            }
+            None => {
+                let ton_offset = options.header_size;
+                let tof_offset = ton_offset
+                    .checked_add(ton_size as usize)
+                    .ok_or(ParseError::OffsetOverflow)?;
+                (ton_offset, tof_offset)
+            }
+        };
+        let toc_offset = TOF_ENTRY_SIZE
+            .checked_mul(files_no as usize)
+            .and_then(|x| x.checked_add(tof_offset))
+            .ok_or(ParseError::OffsetOverflow)?;
+        if files_no == 0 || toc_offset > content.len() {
+            return Err(ParseError::InvalidHeader);
+        }
 
         let mut offsets = MiniFsOffsets {
             ton_offset,
+            ton_size: ton_size as usize,
             tof_offset,
             toc_offset,
             // Unknown at this time
             raw_chunks_offset: 0,
+            chunks_base: 0,
         };
 
-        let files = Self::parse_files_internal(&content, &offsets, files_no);
-
-        let chunks_no = files.last().unwrap().chunk_number + 1;
-        offsets.raw_chunks_offset = offsets.toc_offset + (TOC_ENTRY_SIZE * chunks_no as usize);
-        let chunks = Self::parse_chunks_internal(&content, &offsets, chunks_no);
+        let chunks_no = if options.infer_chunks {
+            // The first chunk starts with the LZMA configuration word, right after the table
+            let starts_chunks = |chunks_no: usize| {
+                let offset = offsets.toc_offset + chunks_no * TOC_ENTRY_SIZE;
+                get_offset(content, offset, 4) == LZMA_CONFIGURATION_WORD.to_be_bytes()
+            };
+            (1..)
+                .take_while(|x| offsets.toc_offset + x * TOC_ENTRY_SIZE < content.len())
+                .find(|x| starts_chunks(*x))
+                .ok_or(ParseError::InvalidHeader)? as u32
+        } else {
+            // The last file is in the last chunk
+            let last_file_offset = offsets.tof_offset + (files_no as usize - 1) * TOF_ENTRY_SIZE;
+            endianness
+                .read_u32(&content[last_file_offset + 8..])
+                .checked_add(1)
+                .ok_or(ParseError::OffsetOverflow)?
+        };
+        offsets.raw_chunks_offset = TOC_ENTRY_SIZE
+            .checked_mul(chunks_no as usize)
+            .and_then(|x| x.checked_add(offsets.toc_offset))
+            .ok_or(ParseError::OffsetOverflow)?;
+        if offsets.raw_chunks_offset > content.len().saturating_sub(4) {
+            return Err(ParseError::InvalidHeader);
+        }
+        if !options.absolute_chunk_offsets {
+            offsets.chunks_base = offsets.raw_chunks_offset;
+        }
 
-        // To make sure we are decompressing a minifs filesystem that matches the documentation (https://arxiv.org/html/2407.05064v1),
-        // make sure the LZMA Configuration word is the same
-        if u32::from_be_bytes(
-            get_offset(&content, offsets.raw_chunks_offset, 4)
-                .try_into()
-                .unwrap(),
-        ) != LZMA_CONFIGURATION_WORD
-        {
-            return Err(ParseError::UnsupportedVersion);
+        // Each region must start after the previous one, so no bytes are read as two tables
+        let tables_ordered = offsets.tof_offset < offsets.toc_offset
+            && offsets.toc_offset < offsets.raw_chunks_offset;
+        let ton_separate = match options.ton_offset_field {
+            Some(_) => {
+                offsets.ton_offset >= offsets.raw_chunks_offset
+                    || offsets.ton_offset.saturating_add(offsets.ton_size) <= offsets.tof_offset
+            }
+            None => offsets.ton_offset < offsets.tof_offset,
+        };
+        if !tables_ordered || !ton_separate {
+            return Err(ParseError::RegionsOutOfOrder);
         }
 
-        Ok(Self {
-            header_start,
-            content,
+        Ok(Layout {
+            endianness,
+            files_no,
+            chunks_no,
             offsets,
-            files,
-            chunks,
         })
     }
 
@@ -131,11 +684,12 @@ impl MiniFs {
         content: &[u8],
         offsets: &MiniFsOffsets,
         files_no: u32,
+        endianness: Endianness,
     ) -> Vec<ToFEntry> {
         (0..files_no as usize)
             .map(|offset| {
                 let entry_offset = offsets.tof_offset + offset * TOF_ENTRY_SIZE;
-                ToFEntry::parse(
+                ToFEntry::parse_with_endianness(
                     content
                         .iter()
                         .copied()
@@ -144,6 +698,7 @@ impl MiniFs {
                         .collect::<Vec<u8>>()
                         .try_into()
                         .unwrap(),
+                    endianness,
                 )
             })
             .collect()
@@ -153,11 +708,12 @@ impl MiniFs {
         content: &[u8],
         offsets: &MiniFsOffsets,
         chunks_no: u32,
+        endianness: Endianness,
     ) -> Vec<ToCEntry> {
         (0..chunks_no as usize)
             .map(|offset| {
                 let entry_offset = offsets.toc_offset + offset * TOC_ENTRY_SIZE;
-                ToCEntry::parse(
+                ToCEntry::parse_with_endianness(
                     content
                         .iter()
                         .copied()
@@ -166,6 +722,7 @@ impl MiniFs {
                         .collect::<Vec<u8>>()
                         .try_into()
                         .unwrap(),
+                    endianness,
                 )
             })
             .collect()
@@ -175,79 +732,910 @@ impl MiniFs {
         self.header_start
     }
 
+    /// Whether the first chunk starts with the expected LZMA configuration word. Always true for
+    /// filesystems returned by [`MiniFs::parse`].
+    pub fn is_validated(&self) -> bool {
+        self.validated
+    }
+
+    pub fn get_endianness(&self) -> Endianness {
+        self.endianness
+    }
+
     pub fn get_files_no(&self) -> usize {
         self.files.len()
     }
 
-    pub fn extract(&self) -> Vec<DecompressedFile> {
-        let decompressed_chunks = self
-            .chunks
+    pub fn get_chunks_no(&self) -> usize {
+        self.chunks_no as usize
+    }
+
+    /// Sum of the uncompressed sizes of all files
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|x| x.file_size as u64).sum()
+    }
+
+    /// Sum of the compressed sizes of all chunks
+    pub fn total_compressed_size(&self) -> u64 {
+        self.chunk_entries()
             .iter()
-            .map(|x| {
-                let compressed_chunk = get_offset(
-                    &self.content,
-                    self.offsets.raw_chunks_offset + x.chunk_offset as usize,
-                    x.chunk_size.try_into().unwrap(),
-                );
-                let decompressed_chunk =
-                    lzma::decompress(&compressed_chunk).expect("Couldn't decompress LZMA chunk");
-                if decompressed_chunk.len() != x.decompressed_size as usize {
-                    panic!("LZMA decompressed chunk doesn't match size");
-                }
+            .map(|x| x.chunk_size as u64)
+            .sum()
+    }
 
-                decompressed_chunk
-            })
-            .collect::<Vec<Vec<u8>>>();
-        println!("[+] Decompressed {} chunks", decompressed_chunks.len());
+    /// Size the image should have according to its tables: up to the end of the final chunk, or of
+    /// the Table of Names when it is placed after the chunks
+    pub fn expected_size(&self) -> u64 {
+        let image_len = self.image_len() as u64;
+        if self.offsets.ton_offset >= self.offsets.raw_chunks_offset {
+            let ton_end = self.offsets.ton_offset as u64 + self.offsets.ton_size as u64;
+            image_len.max(ton_end)
+        } else {
+            image_len
+        }
+    }
 
-        self.files
+    /// Number of bytes from the header to the end of the final chunk, which is where the minifs
+    /// ends within the input
+    pub fn image_len(&self) -> usize {
+        self.chunk_entries()
             .iter()
-            .map(|x| {
-                let path = read_string(
-                    &self.content,
-                    self.offsets.ton_offset + x.ton_path_offset as usize,
-                );
-                let filename = read_string(
-                    &self.content,
-                    self.offsets.ton_offset + x.ton_file_name_offset as usize,
-                );
-
-                let data = get_offset(
-                    &decompressed_chunks[x.chunk_number as usize],
-                    x.offset_in_chunk as usize,
-                    x.file_size as usize,
-                );
-
-                DecompressedFile {
-                    path,
-                    filename,
+            .map(|x| x.chunk_offset as usize + x.chunk_size as usize)
+            .max()
+            .unwrap_or(0)
+            .saturating_add(self.offsets.chunks_base)
+    }
+
+    /// Raw bytes of the minifs image, from the header to the end of the final chunk, without the
+    /// data surrounding it in the input
+    pub fn image_bytes(&self) -> &[u8] {
+        self.region(0, self.image_len())
+    }
+
+    /// Number of bytes available from the header to the end of the input
+    pub fn content_len(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Raw bytes of the Table of Names
+    pub fn ton_bytes(&self) -> &[u8] {
+        self.region(
+            self.offsets.ton_offset,
+            self.offsets
+                .ton_offset
+                .saturating_add(self.offsets.ton_size),
+        )
+    }
+
+    /// Raw bytes of the Table of Files
+    pub fn tof_bytes(&self) -> &[u8] {
+        self.region(self.offsets.tof_offset, self.offsets.toc_offset)
+    }
+
+    /// Raw bytes of the Table of Chunks
+    pub fn toc_bytes(&self) -> &[u8] {
+        self.region(self.offsets.toc_offset, self.offsets.raw_chunks_offset)
+    }
+
+    fn region(&self, start: usize, end: usize) -> &[u8] {
+        let end = end.min(self.content.len());
+        &self.content[start.min(end)..end]
+    }
+
+    pub fn list(&self) -> Vec<FileInfo<'_>> {
+        self.iter_files().collect()
+    }
+
+    /// Same as [`MiniFs::list`], resolving the names of each entry only as it is reached
+    pub fn iter_files(&self) -> impl Iterator<Item = FileInfo<'_>> {
+        self.files.iter().enumerate().map(|(index, x)| {
+            let (path, filename) = self.names(x);
+            FileInfo {
+                index,
+                path,
+                filename,
+                entry: x,
+            }
+        })
+    }
+
+    /// Looks up a file by its `path/filename`
+    pub fn find(&self, path: &str, ignore_case: bool) -> Option<FileInfo<'_>> {
+        let path = path.trim_start_matches('/');
+        self.list().into_iter().find(|x| {
+            if ignore_case {
+                x.full_path().to_lowercase() == path.to_lowercase()
+            } else {
+                x.full_path() == path
+            }
+        })
+    }
+
+    /// Iterates over the entries along with their resolved `path/filename`
+    pub fn entries(&self) -> impl Iterator<Item = (&ToFEntry, String)> {
+        self.files.iter().map(|x| {
+            let (path, filename) = self.names(x);
+            (x, join_path(&path, &filename))
+        })
+    }
+
+    /// Resolved `path/filename` of the file at `index` in the Table of Files, without decompressing
+    /// anything
+    pub fn path_of(&self, index: usize) -> Option<String> {
+        let (path, filename) = self.names(self.files.get(index)?);
+        Some(join_path(&path, &filename))
+    }
+
+    /// Path and filename of `entry`. Names without a NUL within the scan limit, which are only
+    /// left with [`MiniFsOptions::trust_input`], are empty.
+    fn names(&self, entry: &ToFEntry) -> (String, String) {
+        let resolve_name = |ton_offset| self.resolve_name(ton_offset).unwrap_or_default();
+        if self.combined_names && entry.ton_path_offset == entry.ton_file_name_offset {
+            let full_path = resolve_name(entry.ton_path_offset);
+            return match full_path.rsplit_once('/') {
+                Some((path, filename)) => (path.to_string(), filename.to_string()),
+                None => (String::new(), full_path),
+            };
+        }
+
+        (
+            resolve_name(entry.ton_path_offset),
+            resolve_name(entry.ton_file_name_offset),
+        )
+    }
+
+    /// Reads the name at `ton_offset` from the start of the Table of Names, as stored in the
+    /// `ton_path_offset` and `ton_file_name_offset` of the Table of Files entries. Fails if there
+    /// is no NUL within [`MiniFsOptions::name_scan_limit`] bytes.
+    pub fn resolve_name(&self, ton_offset: u32) -> Result<String, ParseError> {
+        let offset = self.offsets.ton_offset + ton_offset as usize;
+        read_string(&self.content, offset, self.name_scan_limit)
+    }
+
+    pub fn extract(&self) -> Result<Vec<DecompressedFile>, ParseError> {
+        self.extract_where(|_| true)
+    }
+
+    /// Extracts only the files matching `predicate`. Chunks which aren't referenced by any of the
+    /// selected files are never decompressed. The warnings are dropped, see
+    /// [`MiniFs::extract_where_reported`].
+    pub fn extract_where<F>(&self, predicate: F) -> Result<Vec<DecompressedFile>, ParseError>
+    where
+        F: Fn(&FileInfo) -> bool,
+    {
+        self.extract_where_reported(predicate).map(|x| x.files)
+    }
+
+    /// Same as `extract_where`, also returning how long each decompressed chunk took, in the
+    /// order they were decompressed
+    pub fn extract_where_timed<F>(
+        &self,
+        predicate: F,
+    ) -> Result<(Vec<DecompressedFile>, Vec<ChunkTiming>), ParseError>
+    where
+        F: Fn(&FileInfo) -> bool,
+    {
+        self.extract_where_reported(predicate)
+            .map(|x| (x.files, x.timings))
+    }
+
+    /// Same as `extract_where`, also returning the chunk timings and the anomalies of the chunks,
+    /// such as truncated padding or partial chunks
+    pub fn extract_where_reported<F>(&self, predicate: F) -> Result<Extracted, ParseError>
+    where
+        F: Fn(&FileInfo) -> bool,
+    {
+        let selected = self
+            .list()
+            .into_iter()
+            .filter(|x| predicate(x))
+            .collect::<Vec<FileInfo>>();
+
+        self.extract_internal(selected, &mut BTreeMap::new())
+    }
+
+    /// Decompresses the chunks spanned by `selected` which aren't in `decompressed_chunks` yet,
+    /// keeping them there, and slices the files out of them
+    fn extract_internal(
+        &self,
+        selected: Vec<FileInfo>,
+        decompressed_chunks: &mut BTreeMap<u32, Vec<u8>>,
+    ) -> Result<Extracted, ParseError> {
+        // A file starting past the end of its chunk means the tables are wrong, rather than the
+        // chunk being truncated
+        for file in selected.iter().filter(|_| self.check_entries) {
+            let Some(chunk) = self.chunk_entries().get(file.entry.chunk_number as usize) else {
+                continue;
+            };
+            let offset_in_chunk = file.entry.offset_in_chunk;
+            if offset_in_chunk > chunk.decompressed_size
+                || (offset_in_chunk == chunk.decompressed_size && file.entry.file_size > 0)
+            {
+                return Err(ParseError::FileOffsetBeyondChunk {
+                    path: file.full_path(),
+                    offset_in_chunk,
+                    decompressed_size: chunk.decompressed_size,
+                });
+            }
+        }
+
+        let mut timings = Vec::new();
+        let mut warnings = Vec::new();
+        for file in selected.iter() {
+            for chunk_number in self.chunks_spanned(file.entry) {
+                if let Entry::Vacant(entry) = decompressed_chunks.entry(chunk_number) {
+                    let start = Instant::now();
+                    entry.insert(self.decompress_chunk(chunk_number, &mut warnings)?);
+                    timings.push(ChunkTiming {
+                        chunk_number,
+                        elapsed: start.elapsed(),
+                    });
+                }
+            }
+        }
+
+        let files = selected
+            .into_iter()
+            .filter_map(|x| {
+                let file_size = x.entry.file_size as usize;
+                let chunks = self.chunks_spanned(x.entry);
+
+                let mut data = Vec::with_capacity(file_size);
+                let mut offset_in_chunk = x.entry.offset_in_chunk as usize;
+                for chunk_number in chunks.clone() {
+                    data.extend(get_offset(
+                        &decompressed_chunks[&chunk_number],
+                        offset_in_chunk,
+                        file_size - data.len(),
+                    ));
+                    offset_in_chunk = 0;
+                }
+
+                // Only reachable with partial chunks, see `MiniFsOptions::tolerate_size_mismatch`
+                if data.len() < file_size {
+                    if data.is_empty() {
+                        warnings.push(ParseWarning::MissingFile(x.full_path()));
+                        return None;
+                    }
+                    warnings.push(ParseWarning::PartialFile {
+                        path: x.full_path(),
+                        recovered: data.len(),
+                        size: file_size,
+                    });
+                }
+
+                Some(DecompressedFile {
+                    index: x.index,
+                    path: x.path,
+                    filename: x.filename,
+                    chunks,
                     data,
+                })
+            })
+            .collect::<Vec<DecompressedFile>>();
+
+        Ok(Extracted {
+            files,
+            timings,
+            warnings,
+        })
+    }
+
+    /// Extracts the files matching `predicate` chunk by chunk, yielding the files starting in each
+    /// chunk along with the chunk number. Names are resolved once, and each chunk is decompressed
+    /// once, when the first file needing it comes up, then dropped once no later file can need it.
+    /// Chunks without any matching file are never decompressed.
+    pub fn extract_by_chunk_where<F>(
+        &self,
+        predicate: F,
+    ) -> impl Iterator<Item = (u32, Result<Extracted, ParseError>)> + '_
+    where
+        F: Fn(&FileInfo) -> bool,
+    {
+        let mut files_by_chunk = (0..self.chunks_no)
+            .map(|_| Vec::new())
+            .collect::<Vec<Vec<FileInfo>>>();
+        for file in self.iter_files().filter(|x| predicate(x)) {
+            files_by_chunk[file.entry.chunk_number as usize].push(file);
+        }
+
+        let mut decompressed_chunks = BTreeMap::new();
+        files_by_chunk
+            .into_iter()
+            .zip(0..)
+            .map(move |(selected, chunk_number)| {
+                let extracted = self.extract_internal(selected, &mut decompressed_chunks);
+                // Later files start in later chunks, and only span forward
+                decompressed_chunks = decompressed_chunks.split_off(&(chunk_number + 1));
+                (chunk_number, extracted)
+            })
+    }
+
+    /// Decompresses the files one chunk at a time and passes them to `f`, until it breaks. The
+    /// chunks past the ones holding the file it stops at are never decompressed.
+    pub fn extract_until<F>(&self, mut f: F) -> Result<(), ParseError>
+    where
+        F: FnMut(&DecompressedFile) -> ControlFlow<()>,
+    {
+        for (_, extracted) in self.extract_by_chunk_where(|_| true) {
+            for file in extracted?.files.iter() {
+                if f(file).is_break() {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Extracts every file into `options.output_dir`, one chunk at a time, and reports what
+    /// happened instead of stopping at the first failure. The same as [`crate::write_files`] with
+    /// [`write_atomic`], without holding more than a few chunks at once.
+    pub fn run_extraction(&self, options: &ExtractionOptions) -> ExtractionReport {
+        let files = self.list();
+        let mut tree = OutputTree::new(
+            options,
+            files.iter().map(|x| (x.path.as_str(), x.filename.as_str())),
+        );
+        let mut write = |file: &DecompressedFile, file_path: &Path| {
+            write_atomic(file_path, file.data.chunks(WRITE_PIECE_SIZE))
+        };
+
+        for (chunk_number, extracted) in self.extract_by_chunk_where(|_| true) {
+            match extracted {
+                Ok(extracted) => {
+                    tree.warn(extracted.warnings);
+                    for file in extracted.files.iter() {
+                        tree.write(file, &mut write);
+                    }
                 }
+                Err(e) => {
+                    for file in files
+                        .iter()
+                        .filter(|x| x.entry.chunk_number == chunk_number)
+                    {
+                        tree.fail(file.full_path(), ExtractionProblem::Failed(e.to_string()));
+                    }
+                }
+            }
+        }
+
+        tree.finish()
+    }
+
+    /// Extracts every file on tokio's blocking threads, one chunk at a time, sending the files
+    /// through the returned channel as they are decompressed. The first error is sent last.
+    #[cfg(feature = "tokio")]
+    pub async fn extract_async(
+        &self,
+    ) -> tokio::sync::mpsc::Receiver<Result<DecompressedFile, ParseError>> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(ASYNC_CHANNEL_CAPACITY);
+        let minifs = self.clone();
+        tokio::task::spawn_blocking(move || {
+            for (_, extracted) in minifs.extract_by_chunk_where(|_| true) {
+                let files = match extracted {
+                    Ok(extracted) => extracted.files,
+                    Err(e) => {
+                        let _ = sender.blocking_send(Err(e));
+                        return;
+                    }
+                };
+                for file in files {
+                    // The receiver was dropped, nobody wants the remaining files
+                    if sender.blocking_send(Ok(file)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        receiver
+    }
+
+    /// Decompresses every chunk, checking it against its declared size, without resolving names or
+    /// slicing files. Returns the total number of decompressed bytes and the anomalies of the
+    /// chunks.
+    pub fn decompress_all(&self) -> Result<(u64, Vec<ParseWarning>), ParseError> {
+        let mut total = 0;
+        let mut warnings = Vec::new();
+        for chunk_number in 0..self.chunk_entries().len() as u32 {
+            total += self.decompress_chunk(chunk_number, &mut warnings)?.len() as u64;
+        }
+
+        Ok((total, warnings))
+    }
+
+    /// Returns the chunks holding the data of `entry`. A file normally lives within a single chunk,
+    /// but if `offset_in_chunk + file_size` exceeds the chunk's decompressed size, the data
+    /// continues into the following chunks.
+    pub fn chunks_spanned(&self, entry: &ToFEntry) -> Range<u32> {
+        let first = entry.chunk_number;
+        let mut last = first;
+        let mut end = entry.offset_in_chunk as u64 + entry.file_size as u64;
+
+        while let Some(chunk) = self.chunk_entries().get(last as usize) {
+            if end <= chunk.decompressed_size as u64
+                || last as usize + 1 >= self.chunk_entries().len()
+            {
+                break;
+            }
+
+            end -= chunk.decompressed_size as u64;
+            last += 1;
+        }
+
+        first..last + 1
+    }
+
+    /// Pairs of TOF indices of files whose data overlap within the chunk they share, each paired
+    /// with the earlier file reaching the furthest. Empty files never overlap.
+    pub fn overlapping_files(&self) -> Vec<(usize, usize)> {
+        let mut ranges = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.file_size > 0)
+            .map(|(index, x)| {
+                let start = x.offset_in_chunk as u64;
+                (x.chunk_number, start, start + x.file_size as u64, index)
             })
-            .collect::<Vec<DecompressedFile>>()
+            .collect::<Vec<(u32, u64, u64, usize)>>();
+        ranges.sort();
+
+        let mut overlaps = Vec::new();
+        let mut furthest: Option<(u32, u64, usize)> = None;
+        for (chunk_number, start, end, index) in ranges {
+            match furthest {
+                Some((chunk, furthest_end, furthest_index)) if chunk == chunk_number => {
+                    if start < furthest_end {
+                        overlaps.push((furthest_index, index));
+                    }
+                    if end > furthest_end {
+                        furthest = Some((chunk_number, end, index));
+                    }
+                }
+                _ => furthest = Some((chunk_number, end, index)),
+            }
+        }
+
+        overlaps
+    }
+
+    /// Compressed bytes of the chunk holding the file at `index` in the Table of Files, to
+    /// reproduce its decompression with external tools
+    pub fn compressed_chunk_for_file(&self, index: usize) -> Option<&[u8]> {
+        let file = self.files.get(index)?;
+        let chunk = self.chunk_entries().get(file.chunk_number as usize)?;
+        self.compressed_bytes(chunk).ok()
+    }
+
+    /// Iterates over the Table of Chunks entries along with their compressed bytes, clamped to the
+    /// image
+    pub fn chunks(&self) -> impl Iterator<Item = (&ToCEntry, &[u8])> {
+        self.chunk_entries()
+            .iter()
+            .map(|x| (x, self.compressed_bytes(x).unwrap_or_default()))
+    }
+
+    /// Decompressed bytes of the whole chunk `chunk_number`, regardless of the files it holds, or
+    /// `None` if there is no such chunk. The anomalies of the chunk are added to `warnings`.
+    pub fn decompressed_chunk(
+        &self,
+        chunk_number: usize,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Option<Result<Vec<u8>, ParseError>> {
+        self.chunk_entries().get(chunk_number)?;
+        Some(self.decompress_chunk(chunk_number as u32, warnings))
+    }
+
+    /// Offsets of the compressed bytes of chunk `chunk_number` from the header, before clamping to
+    /// the image
+    pub fn chunk_bounds(&self, chunk_number: usize) -> Option<Range<usize>> {
+        let chunk = self.chunk_entries().get(chunk_number)?;
+        let start = self
+            .offsets
+            .chunks_base
+            .checked_add(chunk.chunk_offset as usize)?;
+        let end = start.checked_add(chunk.chunk_size as usize)?;
+        Some(start..end)
+    }
+
+    /// Entries of the Table of Chunks, parsed on the first call if they were deferred
+    fn chunk_entries(&self) -> &[ToCEntry] {
+        self.chunks.get_or_init(|| {
+            Self::parse_chunks_internal(
+                &self.content,
+                &self.offsets,
+                self.chunks_no,
+                self.endianness,
+            )
+        })
+    }
+
+    fn compressed_bytes(&self, chunk: &ToCEntry) -> Result<&[u8], ParseError> {
+        let chunk_start = self
+            .offsets
+            .chunks_base
+            .checked_add(chunk.chunk_offset as usize)
+            .ok_or(ParseError::OffsetOverflow)?;
+        let chunk_end = chunk_start
+            .checked_add(chunk.chunk_size as usize)
+            .ok_or(ParseError::OffsetOverflow)?;
+
+        Ok(self.region(chunk_start, chunk_end))
+    }
+
+    /// Decompresses chunk `chunk_number`, which must exist, truncated to its declared size
+    fn decompress_chunk(
+        &self,
+        chunk_number: u32,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<Vec<u8>, ParseError> {
+        let chunk = &self.chunk_entries()[chunk_number as usize];
+        let compressed = self.compressed_bytes(chunk)?;
+        let expected = chunk.decompressed_size as usize;
+        let mut decompressed_chunk = if self.tolerate_size_mismatch {
+            decompress_partial_chunk(compressed, expected)
+        } else {
+            decompress_untruncated_chunk(compressed, expected)?
+        };
+
+        let size = decompressed_chunk.len();
+        if size < expected {
+            warnings.push(ParseWarning::PartialChunk {
+                chunk_number,
+                size,
+                expected,
+            });
+        } else if size > expected {
+            // Some LZMA streams carry trailing padding, files are only sliced from the declared size
+            warnings.push(ParseWarning::OversizedChunk {
+                chunk_number,
+                size,
+                expected,
+            });
+            decompressed_chunk.truncate(expected);
+        }
+
+        Ok(decompressed_chunk)
     }
 }
 
+/// Decompresses a raw LZMA chunk and checks it against the `expected_size` declared in its Table
+/// of Chunks entry. Any output past that size is dropped.
+pub fn decompress_chunk(compressed: &[u8], expected_size: usize) -> Result<Vec<u8>, ParseError> {
+    let mut decompressed_chunk = decompress_untruncated_chunk(compressed, expected_size)?;
+    decompressed_chunk.truncate(expected_size);
+    Ok(decompressed_chunk)
+}
+
+/// Same as [`decompress_chunk`], keeping any output past `expected_size`
+fn decompress_untruncated_chunk(
+    compressed: &[u8],
+    expected_size: usize,
+) -> Result<Vec<u8>, ParseError> {
+    let mut decompressed_chunk = lzma::decompress(compressed).map_err(ParseError::Decompression)?;
+    if decompressed_chunk.len() < expected_size {
+        // The chunk may be made of several concatenated LZMA streams
+        decompressed_chunk = Vec::new();
+        decompress_streams(compressed, expected_size, &mut decompressed_chunk)
+            .map_err(ParseError::Decompression)?;
+    }
+    if decompressed_chunk.len() < expected_size {
+        return Err(ParseError::ShortChunk {
+            expected: expected_size,
+            actual: decompressed_chunk.len(),
+        });
+    }
+
+    Ok(decompressed_chunk)
+}
+
+/// Same as [`decompress_untruncated_chunk`], but a corrupted chunk returns whatever could be
+/// decompressed before the corruption instead of failing
+fn decompress_partial_chunk(compressed: &[u8], expected_size: usize) -> Vec<u8> {
+    let mut decompressed_chunk = lzma::decompress(compressed).unwrap_or_default();
+    if decompressed_chunk.len() < expected_size {
+        let mut streams = Vec::new();
+        // Whatever was decoded before an error is kept
+        let _ = decompress_streams(compressed, expected_size, &mut streams);
+        if streams.len() > decompressed_chunk.len() {
+            decompressed_chunk = streams;
+        }
+    }
+
+    decompressed_chunk
+}
+
+/// Decompresses consecutive LZMA streams from `compressed` into `decompressed` until
+/// `expected_size` bytes are produced or the input is exhausted
+fn decompress_streams(
+    mut compressed: &[u8],
+    expected_size: usize,
+    decompressed: &mut Vec<u8>,
+) -> Result<(), LzmaError> {
+    while decompressed.len() < expected_size && !compressed.is_empty() {
+        let remaining = compressed.len();
+        {
+            // Feed the decoder one byte at a time, so it doesn't read past the end of its stream
+            let mut reader =
+                LzmaReader::with_capacity(1, &mut compressed, Direction::Decompress, 0)?;
+            reader.read_to_end(decompressed)?;
+        }
+
+        if compressed.len() == remaining {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Cheaply checks if `content` holds a minifs: a plausible header whose first chunk starts with
+/// the LZMA configuration word. None of the tables are parsed.
+pub fn is_valid(content: &[u8]) -> bool {
+    let options = MiniFsOptions::default();
+    magic_offsets(content).any(|offset| {
+        let content = &content[offset..];
+        MiniFs::parse_layout(content, &options)
+            .is_ok_and(|layout| has_configuration_word(content, &layout.offsets))
+    })
+}
+
+/// Checks every `MINIFS` magic in `content`, to locate the filesystems of multi-partition dumps
+pub fn scan_magics(content: &[u8]) -> Vec<MagicCandidate> {
+    let options = MiniFsOptions::default();
+    magic_offsets(content)
+        .map(|offset| {
+            let layout = MiniFs::parse_layout(&content[offset..], &options);
+            MagicCandidate {
+                offset,
+                header_plausible: layout.is_ok(),
+                configuration_word_matches: layout.is_ok_and(|layout| {
+                    has_configuration_word(&content[offset..], &layout.offsets)
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Offsets of all the `MINIFS` magics in `content`
+fn magic_offsets(content: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    let mut search_start = 0;
+    std::iter::from_fn(move || {
+        let offset = search_start + find_bytes(&content[search_start..], HEADER_MAGIC_NUMBER)?;
+        search_start = offset + 1;
+        Some(offset)
+    })
+}
+
+/// Whether any two non-empty chunks share bytes of the image
+fn has_overlaps(chunks: &[ToCEntry]) -> bool {
+    let mut chunk_ranges = chunks
+        .iter()
+        .filter(|x| x.chunk_size > 0)
+        .map(|x| {
+            (
+                x.chunk_offset as u64,
+                x.chunk_offset as u64 + x.chunk_size as u64,
+            )
+        })
+        .collect::<Vec<(u64, u64)>>();
+    chunk_ranges.sort();
+    chunk_ranges.windows(2).any(|x| x[1].0 < x[0].1)
+}
+
+/// To make sure we are decompressing a minifs filesystem that matches the documentation
+/// (https://arxiv.org/html/2407.05064v1), make sure the LZMA Configuration word is the same
+fn has_configuration_word(content: &[u8], offsets: &MiniFsOffsets) -> bool {
+    u32::from_be_bytes(
+        get_offset(content, offsets.raw_chunks_offset, 4)
+            .try_into()
+            .unwrap(),
+    ) == LZMA_CONFIGURATION_WORD
+}
+
 fn find_bytes(content: &[u8], pattern: &[u8]) -> Option<usize> {
     content
         .windows(pattern.len())
         .position(|window| window == pattern)
 }
 
+/// Splits a Table of Names path into its directories. Both '/' and '\\' are separators, since
+/// images built on Windows hosts use the latter, and empty or `.` components are dropped.
+pub fn path_components(path: &str) -> impl Iterator<Item = &str> {
+    path.split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != ".")
+}
+
+fn join_path(path: &str, filename: &str) -> String {
+    let mut full_path = path_components(path).collect::<Vec<&str>>();
+    full_path.push(filename);
+    full_path.join("/")
+}
+
 fn get_offset(content: &[u8], offset: usize, len: usize) -> Vec<u8> {
     content.iter().skip(offset).take(len).copied().collect()
 }
 
-fn read_string(content: &[u8], offset: usize) -> String {
+/// Reads the NUL-terminated string at `offset`, scanning at most `limit` bytes for the NUL
+fn read_string(content: &[u8], offset: usize, limit: usize) -> Result<String, ParseError> {
     let mut data = String::new();
 
-    for byte in content.iter().skip(offset) {
+    for byte in content.iter().skip(offset).take(limit) {
         if *byte == 0_u8 {
-            return data;
+            return Ok(data);
         }
 
         data.push(*byte as char);
     }
 
-    data
+    Err(ParseError::UnterminatedString)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `root:x:0:0\nhello\n` compressed in the LZMA alone format, as in real images
+    const CHUNK: &[u8] = &[
+        0x5d, 0x00, 0x00, 0x80, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x39,
+        0x1b, 0xec, 0xe8, 0x3a, 0x2d, 0x7f, 0xca, 0x5c, 0x62, 0x09, 0x96, 0x00, 0x06, 0xf4, 0xc6,
+        0x00, 0x10, 0xf2, 0x6e, 0xcf, 0xff, 0xfb, 0x10, 0x80, 0x00,
+    ];
+    const CHUNK_SIZE: u32 = 17;
+    const NAMES: &[u8] = b"etc\0passwd\0hosts\0";
+    /// `etc/passwd` and `etc/hosts`, both in the single chunk
+    const FILES: &[[u32; 5]] = &[[0, 4, 0, 0, 11], [0, 11, 0, 11, 6]];
+    const CHUNKS: &[[u32; 3]] = &[[0, CHUNK.len() as u32, CHUNK_SIZE]];
+
+    /// A minifs with the documented layout, its raw chunks made of `CHUNK` alone
+    fn image(
+        endianness: Endianness,
+        names: &[u8],
+        files: &[[u32; 5]],
+        chunks: &[[u32; 3]],
+    ) -> Vec<u8> {
+        let write_u32 = |image: &mut Vec<u8>, x: u32| match endianness {
+            Endianness::Big => image.extend(x.to_be_bytes()),
+            Endianness::Little => image.extend(x.to_le_bytes()),
+        };
+
+        let mut image = HEADER_MAGIC_NUMBER.to_vec();
+        image.resize(0x14, 0);
+        write_u32(&mut image, files.len() as u32);
+        write_u32(&mut image, 0);
+        write_u32(&mut image, names.len() as u32);
+        image.extend(names);
+        for x in files.iter().flatten().chain(chunks.iter().flatten()) {
+            write_u32(&mut image, *x);
+        }
+        image.extend(CHUNK);
+        image
+    }
+
+    #[test]
+    fn parses_and_extracts() {
+        let parsed = MiniFs::parse(image(Endianness::Big, NAMES, FILES, CHUNKS)).unwrap();
+        assert_eq!(parsed.warnings, Vec::new());
+        assert_eq!(parsed.minifs.endianness, Endianness::Big);
+
+        let files = parsed.minifs.extract().unwrap();
+        let files = files
+            .iter()
+            .map(|x| (x.full_path(), x.data.as_slice()))
+            .collect::<Vec<(String, &[u8])>>();
+        assert_eq!(
+            files,
+            vec![
+                ("etc/passwd".to_string(), b"root:x:0:0\n".as_slice()),
+                ("etc/hosts".to_string(), b"hello\n".as_slice()),
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_little_endian() {
+        let parsed = MiniFs::parse(image(Endianness::Little, NAMES, FILES, CHUNKS)).unwrap();
+        assert_eq!(parsed.minifs.endianness, Endianness::Little);
+        assert_eq!(parsed.minifs.list().len(), 2);
+        assert_eq!(parsed.minifs.extract().unwrap()[1].data, b"hello\n");
+    }
+
+    #[test]
+    fn skips_false_positive_magic() {
+        let mut content = HEADER_MAGIC_NUMBER.to_vec();
+        content.resize(HEADER_SIZE, 0);
+        content.extend(image(Endianness::Big, NAMES, FILES, CHUNKS));
+
+        let parsed = MiniFs::parse(content).unwrap();
+        assert_eq!(
+            parsed.warnings,
+            vec![
+                ParseWarning::SkippedMagic(0),
+                ParseWarning::SkippedPadding(HEADER_SIZE)
+            ]
+        );
+        assert_eq!(parsed.minifs.list().len(), 2);
+    }
+
+    #[test]
+    fn measures_trailing_data_from_the_last_chunk() {
+        let mut content = image(Endianness::Big, NAMES, FILES, CHUNKS);
+        let image_len = content.len();
+        content.extend([0; 16]);
+
+        let parsed = MiniFs::parse(content).unwrap();
+        assert_eq!(parsed.minifs.expected_size(), image_len as u64);
+        assert_eq!(parsed.warnings, vec![ParseWarning::TrailingData(16)]);
+    }
+
+    #[test]
+    fn rejects_regions_out_of_order() {
+        // Without names, the Table of Names and the Table of Files start at the same offset
+        let content = image(Endianness::Big, b"", FILES, CHUNKS);
+        assert!(matches!(
+            MiniFs::parse(content),
+            Err(ParseError::RegionsOutOfOrder)
+        ));
+    }
+
+    #[test]
+    fn rejects_overlapping_chunks() {
+        let files = [[0, 4, 0, 0, 11], [0, 11, 1, 0, 6]];
+        let chunks = [
+            [0, CHUNK.len() as u32, CHUNK_SIZE],
+            [1, CHUNK.len() as u32 - 1, CHUNK_SIZE],
+        ];
+        let content = image(Endianness::Big, NAMES, &files, &chunks);
+        assert!(matches!(
+            MiniFs::parse(content),
+            Err(ParseError::OverlappingChunks)
+        ));
+    }
+
+    #[test]
+    fn rejects_chunk_number_out_of_range() {
+        // The number of chunks is taken from the last file
+        let files = [[0, 4, 1, 0, 11], [0, 11, 0, 11, 6]];
+        let content = image(Endianness::Big, NAMES, &files, CHUNKS);
+        assert!(matches!(
+            MiniFs::parse(content),
+            Err(ParseError::ChunkNumberOutOfRange {
+                chunk_number: 1,
+                chunks_no: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_name_offset_out_of_range() {
+        let files = [[0, 4, 0, 0, 11], [0, NAMES.len() as u32, 0, 11, 6]];
+        let content = image(Endianness::Big, NAMES, &files, CHUNKS);
+        assert!(matches!(
+            MiniFs::parse(content),
+            Err(ParseError::InvalidNameOffset)
+        ));
+    }
+
+    #[test]
+    fn rejects_unterminated_names() {
+        let content = image(Endianness::Big, b"etcpasswd", &[[0, 3, 0, 0, 11]], CHUNKS);
+        let options = MiniFsOptions::new().name_scan_limit(4);
+        assert!(matches!(
+            MiniFs::parse_with_options(content, &options),
+            Err(ParseError::UnterminatedString)
+        ));
+    }
+
+    #[test]
+    fn splits_paths_on_both_separators() {
+        let components = |path| path_components(path).collect::<Vec<&str>>();
+        assert_eq!(components("usr/share"), ["usr", "share"]);
+        assert_eq!(components("usr\\share"), ["usr", "share"]);
+        assert_eq!(components("/usr//./share/"), ["usr", "share"]);
+        assert_eq!(components(""), Vec::<&str>::new());
+        // Parent components are kept, for the extraction to reject
+        assert_eq!(components("../etc"), ["..", "etc"]);
+    }
 }
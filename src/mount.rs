@@ -0,0 +1,226 @@
+//! Read-only FUSE view of a minifs, decompressing the files as they are read
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use fuser::{
+    Config, Errno, FileAttr, FileHandle, FileType, Filesystem, Generation, INodeNo, LockOwner,
+    MountOption, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+use minifs_extractor::{path_components, MiniFs};
+
+/// The image never changes while mounted
+const TTL: Duration = Duration::from_secs(3600);
+
+struct Node {
+    parent: u64,
+    /// TOF index of the file, `None` for directories
+    file_index: Option<usize>,
+    size: u64,
+    children: BTreeMap<String, u64>,
+}
+
+pub struct MiniFsMount {
+    minifs: MiniFs,
+    /// Indexed by inode number - 1, the root directory comes first
+    nodes: Vec<Node>,
+    /// Data of the last file read, as reads of a file come in consecutive pieces
+    last_read: Mutex<Option<(usize, Vec<u8>)>>,
+}
+
+impl MiniFsMount {
+    pub fn new(minifs: MiniFs) -> Self {
+        let mut nodes = vec![Node {
+            parent: INodeNo::ROOT.0,
+            file_index: None,
+            size: 0,
+            children: BTreeMap::new(),
+        }];
+
+        for file in minifs.list() {
+            let mut parent = INodeNo::ROOT.0;
+            for component in path_components(&file.path) {
+                parent = child(&mut nodes, parent, component, None, 0);
+            }
+
+            // Entries without a filename mark explicit directories
+            if !file.filename.is_empty() {
+                child(
+                    &mut nodes,
+                    parent,
+                    &file.filename,
+                    Some(file.index),
+                    file.entry.file_size as u64,
+                );
+            }
+        }
+
+        Self {
+            minifs,
+            nodes,
+            last_read: Mutex::new(None),
+        }
+    }
+
+    fn node(&self, ino: INodeNo) -> Option<&Node> {
+        self.nodes.get((ino.0 as usize).checked_sub(1)?)
+    }
+
+    fn attr(&self, ino: u64, node: &Node) -> FileAttr {
+        let (kind, perm, nlink) = match node.file_index {
+            Some(_) => (FileType::RegularFile, 0o444, 1),
+            None => (FileType::Directory, 0o555, 2),
+        };
+        FileAttr {
+            ino: INodeNo(ino),
+            size: node.size,
+            blocks: node.size.div_ceil(512),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm,
+            nlink,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            flags: 0,
+            blksize: 512,
+        }
+    }
+}
+
+/// Inode of the entry `name` in the directory `parent`, added if it doesn't exist yet. The first
+/// entry with a given path wins.
+fn child(
+    nodes: &mut Vec<Node>,
+    parent: u64,
+    name: &str,
+    file_index: Option<usize>,
+    size: u64,
+) -> u64 {
+    if let Some(ino) = nodes[parent as usize - 1].children.get(name) {
+        return *ino;
+    }
+
+    nodes.push(Node {
+        parent,
+        file_index,
+        size,
+        children: BTreeMap::new(),
+    });
+    let ino = nodes.len() as u64;
+    nodes[parent as usize - 1]
+        .children
+        .insert(name.to_string(), ino);
+    ino
+}
+
+impl Filesystem for MiniFsMount {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let ino = self
+            .node(parent)
+            .and_then(|x| x.children.get(name.to_str()?));
+        match ino {
+            Some(ino) => reply.entry(
+                &TTL,
+                &self.attr(*ino, &self.nodes[*ino as usize - 1]),
+                Generation(0),
+            ),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.node(ino) {
+            Some(node) => reply.attr(&TTL, &self.attr(ino.0, node)),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        let Some(index) = self.node(ino).and_then(|x| x.file_index) else {
+            reply.error(Errno::ENOENT);
+            return;
+        };
+
+        let mut last_read = self.last_read.lock().unwrap();
+        if last_read.as_ref().is_none_or(|(x, _)| *x != index) {
+            match self.minifs.extract_where(|x| x.index == index) {
+                Ok(files) => match files.into_iter().next() {
+                    Some(file) => *last_read = Some((index, file.data)),
+                    // The chunk is too short to hold the file
+                    None => {
+                        reply.error(Errno::EIO);
+                        return;
+                    }
+                },
+                Err(e) => {
+                    eprintln!("[-] {}", e);
+                    reply.error(Errno::EIO);
+                    return;
+                }
+            }
+        }
+
+        let data = &last_read.as_ref().unwrap().1;
+        let start = (offset as usize).min(data.len());
+        let end = start.saturating_add(size as usize).min(data.len());
+        reply.data(&data[start..end]);
+    }
+
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(node) = self.node(ino) else {
+            reply.error(Errno::ENOENT);
+            return;
+        };
+
+        let entries = [(ino.0, "."), (node.parent, "..")].into_iter().chain(
+            node.children
+                .iter()
+                .map(|(name, ino)| (*ino, name.as_str())),
+        );
+        for (i, (ino, name)) in entries.enumerate().skip(offset as usize) {
+            let kind = match self.nodes[ino as usize - 1].file_index {
+                Some(_) => FileType::RegularFile,
+                None => FileType::Directory,
+            };
+            // The offset given is the one of the next entry
+            if reply.add(INodeNo(ino), (i + 1) as u64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Serves `minifs` at `mountpoint` until it is unmounted
+pub fn mount(minifs: MiniFs, mountpoint: &Path) -> std::io::Result<()> {
+    let mut config = Config::default();
+    config
+        .mount_options
+        .extend([MountOption::RO, MountOption::FSName("minifs".to_string())]);
+    fuser::mount(MiniFsMount::new(minifs), mountpoint, &config)
+}
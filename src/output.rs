@@ -0,0 +1,322 @@
+//! Writing extracted files into an output directory, without letting any of them escape it
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::File,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{minifs::path_components, DecompressedFile, ParseWarning};
+
+/// Size of the pieces extracted files are written in, to bound each write call
+pub const WRITE_PIECE_SIZE: usize = 1024 * 1024;
+
+/// Where and how `MiniFs::run_extraction` and [`write_files`] write the files
+#[derive(Debug, Clone)]
+pub struct ExtractionOptions {
+    pub output_dir: PathBuf,
+    /// Files whose path has more components are skipped, as corrupt name offsets can read
+    /// arbitrary data as deeply nested paths
+    pub max_path_depth: usize,
+    /// Don't create any directories, the files whose directory doesn't already exist fail
+    pub no_create_dirs: bool,
+}
+
+impl ExtractionOptions {
+    /// Extracts into `output_dir`, skipping paths deeper than 64 components
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            max_path_depth: 64,
+            no_create_dirs: false,
+        }
+    }
+}
+
+/// Outcome of `MiniFs::run_extraction` and [`write_files`]
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionReport {
+    /// Files written into the output directory
+    pub written: usize,
+    /// Entries without a filename, extracted as directories
+    pub directories: usize,
+    /// Files with an unsafe name, which could escape the output directory, or nested too deep
+    pub skipped: usize,
+    /// Files which couldn't be decompressed or written
+    pub failed: usize,
+    /// Bytes written
+    pub total_bytes: u64,
+    pub elapsed: Duration,
+    /// Anomalies of the chunks which were still extracted
+    pub warnings: Vec<ParseWarning>,
+    /// Why each skipped or failed file wasn't written, by `path/filename`
+    pub problems: Vec<(String, ExtractionProblem)>,
+}
+
+/// Why a file wasn't written into the output directory
+#[derive(Debug, Clone)]
+pub enum ExtractionProblem {
+    /// The name could escape the output directory, the file is skipped
+    UnsafeName,
+    /// The path has more than `ExtractionOptions::max_path_depth` components, the file is skipped
+    TooDeep(usize),
+    /// The directory of the file doesn't exist, with `ExtractionOptions::no_create_dirs`
+    MissingDirectory(PathBuf),
+    /// The file couldn't be decompressed or written
+    Failed(String),
+}
+
+impl ExtractionProblem {
+    /// Whether the file was deliberately left out rather than failing
+    pub fn is_skipped(&self) -> bool {
+        matches!(
+            self,
+            ExtractionProblem::UnsafeName | ExtractionProblem::TooDeep(_)
+        )
+    }
+}
+
+impl fmt::Display for ExtractionProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractionProblem::UnsafeName => {
+                write!(f, "its name could escape the output directory")
+            }
+            ExtractionProblem::TooDeep(max_path_depth) => {
+                write!(f, "its path has more than {} components", max_path_depth)
+            }
+            ExtractionProblem::MissingDirectory(dir) => {
+                write!(f, "directory {} doesn't exist", dir.to_string_lossy())
+            }
+            ExtractionProblem::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Writes `files` under `options.output_dir`, each through `write` along with the path it goes to,
+/// and reports what happened instead of stopping at the first failure. All the directories are
+/// created up front. Entries without a filename are only created as directories, and files which
+/// could escape the output directory never reach `write`.
+pub fn write_files<W>(
+    files: &[DecompressedFile],
+    options: &ExtractionOptions,
+    mut write: W,
+) -> ExtractionReport
+where
+    W: FnMut(&DecompressedFile, &Path) -> std::io::Result<()>,
+{
+    let mut tree = OutputTree::new(
+        options,
+        files.iter().map(|x| (x.path.as_str(), x.filename.as_str())),
+    );
+    for file in files {
+        tree.write(file, &mut write);
+    }
+    tree.finish()
+}
+
+/// Directory a file is extracted into and its full path under `output_dir`, the same for entries
+/// without a filename, or `None` if the file could escape the output directory
+pub fn output_paths(output_dir: &Path, path: &str, filename: &str) -> Option<(PathBuf, PathBuf)> {
+    // A filename must be a single path component
+    if filename.contains(['/', '\\']) || filename.contains("..") {
+        return None;
+    }
+
+    let mut dir_path = output_dir.to_path_buf();
+    for component in path_components(path) {
+        dir_path.push(component);
+    }
+    let file_path = if filename.is_empty() {
+        dir_path.clone()
+    } else {
+        dir_path.join(filename)
+    };
+
+    if file_path.components().any(|x| x == Component::ParentDir)
+        || !file_path.starts_with(output_dir)
+    {
+        return None;
+    }
+
+    Some((dir_path, file_path))
+}
+
+/// Writes into a `.part` file first and renames it into place, so an interrupted extraction never
+/// leaves behind a truncated file under the final name. The data is written piece by piece, to bound
+/// each write call.
+pub fn write_atomic<'a>(
+    file_path: &Path,
+    pieces: impl IntoIterator<Item = &'a [u8]>,
+) -> std::io::Result<()> {
+    let mut part_path = file_path.as_os_str().to_owned();
+    part_path.push(".part");
+
+    let mut output_file = File::create(&part_path)?;
+    for piece in pieces {
+        output_file.write_all(piece)?;
+    }
+    std::fs::rename(&part_path, file_path)
+}
+
+/// An output directory being extracted into, accumulating the report. Files can be written as they
+/// are decompressed, see `MiniFs::extract_by_chunk_where`.
+pub struct OutputTree<'a> {
+    options: &'a ExtractionOptions,
+    /// Directories which exist
+    created: BTreeSet<PathBuf>,
+    /// Directories which couldn't be created or don't exist, and what happens to their files
+    unavailable: BTreeMap<PathBuf, ExtractionProblem>,
+    report: ExtractionReport,
+    start: Instant,
+}
+
+impl<'a> OutputTree<'a> {
+    /// Creates every directory needed by the `(path, filename)` of the files once, in sorted order
+    /// so parents come first, before any file is written. The directories of files missing from
+    /// `names` are created when they are written.
+    pub fn new<'b>(
+        options: &'a ExtractionOptions,
+        names: impl Iterator<Item = (&'b str, &'b str)>,
+    ) -> Self {
+        let mut tree = Self {
+            options,
+            created: BTreeSet::new(),
+            unavailable: BTreeMap::new(),
+            report: ExtractionReport::default(),
+            start: Instant::now(),
+        };
+
+        let dirs = names
+            .filter_map(|(path, filename)| tree.paths(path, filename).ok())
+            .map(|(dir, _)| dir)
+            .collect::<BTreeSet<PathBuf>>();
+        for dir in dirs {
+            tree.prepare_dir(dir);
+        }
+
+        tree
+    }
+
+    /// Creates `dir`, or checks it exists with `ExtractionOptions::no_create_dirs`
+    fn prepare_dir(&mut self, dir: PathBuf) {
+        let problem = if self.options.no_create_dirs {
+            (!dir.is_dir()).then(|| ExtractionProblem::MissingDirectory(dir.clone()))
+        } else {
+            std::fs::create_dir_all(&dir)
+                .err()
+                .map(|e| ExtractionProblem::Failed(e.to_string()))
+        };
+        match problem {
+            Some(problem) => {
+                self.unavailable.insert(dir, problem);
+            }
+            None => {
+                self.created.insert(dir);
+            }
+        }
+    }
+
+    /// Directory and full path of a file, checked against the options
+    fn paths(&self, path: &str, filename: &str) -> Result<(PathBuf, PathBuf), ExtractionProblem> {
+        if path_components(path).count() >= self.options.max_path_depth {
+            return Err(ExtractionProblem::TooDeep(self.options.max_path_depth));
+        }
+
+        output_paths(&self.options.output_dir, path, filename).ok_or(ExtractionProblem::UnsafeName)
+    }
+
+    /// Writes `file` through `write`, unless it is skipped or its directory is unavailable
+    pub fn write<W>(&mut self, file: &DecompressedFile, write: &mut W)
+    where
+        W: FnMut(&DecompressedFile, &Path) -> std::io::Result<()>,
+    {
+        let (dir, file_path) = match self.paths(&file.path, &file.filename) {
+            Ok(paths) => paths,
+            Err(problem) => return self.fail(file.full_path(), problem),
+        };
+        if !self.created.contains(&dir) && !self.unavailable.contains_key(&dir) {
+            self.prepare_dir(dir.clone());
+        }
+        if let Some(problem) = self.unavailable.get(&dir) {
+            return self.fail(file.full_path(), problem.clone());
+        }
+
+        // Entries without a filename mark explicit directories
+        if file.filename.is_empty() {
+            self.report.directories += 1;
+            return;
+        }
+
+        match write(file, &file_path) {
+            Ok(_) => {
+                self.report.written += 1;
+                self.report.total_bytes += file.data.len() as u64;
+            }
+            Err(e) => self.fail(file.full_path(), ExtractionProblem::Failed(e.to_string())),
+        }
+    }
+
+    /// Records why the file at `full_path` wasn't written
+    pub fn fail(&mut self, full_path: String, problem: ExtractionProblem) {
+        if problem.is_skipped() {
+            self.report.skipped += 1;
+        } else {
+            self.report.failed += 1;
+        }
+        self.report.problems.push((full_path, problem));
+    }
+
+    /// Records anomalies of the chunks the files were decompressed from
+    pub fn warn(&mut self, warnings: Vec<ParseWarning>) {
+        self.report.warnings.extend(warnings);
+    }
+
+    pub fn finish(mut self) -> ExtractionReport {
+        self.report.elapsed = self.start.elapsed();
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_path(path: &str, filename: &str) -> Option<PathBuf> {
+        output_paths(Path::new("out"), path, filename).map(|(_, file_path)| file_path)
+    }
+
+    #[test]
+    fn joins_paths_under_the_output_dir() {
+        assert_eq!(
+            output_paths(Path::new("out"), "etc", "passwd"),
+            Some((PathBuf::from("out/etc"), PathBuf::from("out/etc/passwd")))
+        );
+        assert_eq!(file_path("a\\b", "c"), Some(PathBuf::from("out/a/b/c")));
+        // Absolute paths are extracted relative to the output directory
+        assert_eq!(
+            file_path("/etc", "passwd"),
+            Some(PathBuf::from("out/etc/passwd"))
+        );
+        // Entries without a filename are their directory
+        assert_eq!(file_path("etc", ""), Some(PathBuf::from("out/etc")));
+    }
+
+    #[test]
+    fn rejects_paths_escaping_the_output_dir() {
+        assert_eq!(file_path("..", "passwd"), None);
+        assert_eq!(file_path("etc/../..", "passwd"), None);
+        assert_eq!(file_path("..\\etc", "passwd"), None);
+        assert_eq!(file_path("etc", ".."), None);
+    }
+
+    #[test]
+    fn rejects_filenames_with_separators() {
+        assert_eq!(file_path("etc", "../passwd"), None);
+        assert_eq!(file_path("etc", "a/b"), None);
+        assert_eq!(file_path("etc", "a\\b"), None);
+        assert_eq!(file_path("", "/etc/passwd"), None);
+    }
+}